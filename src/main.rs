@@ -1,62 +1,249 @@
-mod vm;
-
-use crate::vm::runner::VirtualMachine;
-use crate::vm::disassembler::disassemble_bytecode;
-use crate::vm::assembler::Assembler;
+use flint::vm::runner::{OverflowMode, VirtualMachine, VmConfig};
+use flint::vm::disassembler::{disassemble_bytecode, disassemble_to_source};
+use flint::vm::assembler::Assembler;
+use flint::vm::cfg::ControlFlowGraph;
+use flint::vm::coverage::lcov_report;
+use flint::vm::json::{disassembly_to_json, vm_state_to_json};
+use flint::vm::profiler::Profiler;
+use flint::vm::snapshot;
+use flint::vm::trace::Tracer;
 use std::env;
 use std::fs;
 use std::process;
 
+fn print_usage() {
+    eprintln!("Usage: flint [run|dis|raw] <filename> [options]");
+    eprintln!("       <filename> is Flint assembly, or raw bytecode if it ends in .flb");
+    eprintln!("Commands: run          Execute the program (default)");
+    eprintln!("          dis          Disassemble the code");
+    eprintln!("          raw          Print raw bytecode");
+    eprintln!("Options: -d, --dis     Disassemble the code");
+    eprintln!("         --raw         Print raw bytecode");
+    eprintln!("         --cfg         With dis, print the control-flow graph as Graphviz DOT");
+    eprintln!("         --asm         With dis, print source that reassembles to the same bytecode");
+    eprintln!("         --relative    Assemble jumps to labels as PC-relative, in the shortest form");
+    eprintln!("         --format <text|json>");
+    eprintln!("                       Output format for the disassembly and the final VM state");
+    eprintln!("         --trace       With run, log every executed instruction to stderr");
+    eprintln!("         --trace-range <start>:<end>");
+    eprintln!("                       Only trace addresses in [start, end), decimal or 0x hex");
+    eprintln!("         --profile     With run, print per-opcode counts and hot spots to stderr");
+    eprintln!("         --coverage <file>");
+    eprintln!("                       With run, write an lcov coverage report for the source");
+    eprintln!("         --max-steps <n>");
+    eprintln!("                       With run, pause after executing n instructions");
+    eprintln!("         --snapshot <file>");
+    eprintln!("                       With run, save the VM state to file when execution stops");
+    eprintln!("         --resume <file>");
+    eprintln!("                       With run, restore a snapshot of the same program before running;");
+    eprintln!("                       its overflow, NaN and heap settings replace the flags");
+    eprintln!("         --overflow <wrap|saturate|trap>");
+    eprintln!("                       With run, how integer overflow is handled (default wrap)");
+    eprintln!("         --trap-nan    With run, raise an exception when arithmetic produces NaN");
+    eprintln!("         --heap-limit <slots>");
+    eprintln!("                       With run, cap the heap size; one slot per object and per value");
+}
+
+fn parse_address(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
-        eprintln!("Usage: flint <filename> [options]");
-        eprintln!("Options: -d, --dis    Disassemble the code");
-        eprintln!("         --raw        Print raw bytecode");
+        print_usage();
         process::exit(1);
     }
 
-    let filename = &args[1];
+    // The command is optional so that `flint <filename> [options]` keeps working
+    let (command, rest) = match args[1].as_str() {
+        "run" | "dis" | "raw" => (args[1].as_str(), &args[2..]),
+        _ => ("", &args[1..]),
+    };
 
-    let source = fs::read_to_string(filename).unwrap_or_else(|err| {
-        eprintln!("Error reading file '{}': {}", filename, err);
+    if rest.is_empty() {
+        print_usage();
         process::exit(1);
-    });
+    }
+
+    let filename = &rest[0];
+    let options = &rest[1..];
+    let has_flag = |flag: &str| options.iter().any(|a| a == flag);
+    let option_value = |flag: &str| {
+        options.iter().position(|a| a == flag).and_then(|i| options.get(i + 1))
+    };
+
+    let json_mode = match option_value("--format").map(|f| f.as_str()) {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(other) => {
+            eprintln!("Unknown format '{}', expected 'text' or 'json'", other);
+            process::exit(1);
+        }
+    };
 
     let mut assembler = Assembler::new();
-    
-    // Handle the Result from the assembler
-    match assembler.assemble(&source) {
-        Ok(code) => {
-            let disassemble_mode = args.contains(&"--dis".to_string()) || args.contains(&"-d".to_string());
-            let bytecode_mode = args.contains(&"--raw".to_string());
-
-            if disassemble_mode {
-                let dis = disassemble_bytecode(code);
-                println!("--- DISASSEMBLY (File: {}) ---\n{}", filename, dis);
-            } else if bytecode_mode {
-                println!("--- Raw Bytecode ---");
-                for chunk in code.chunks(10) {
-                    for byte in chunk {
-                        print!("{:02X} ", byte);
+    if has_flag("--relative") {
+        assembler = assembler.with_relative_jumps();
+    }
+
+    // Compiled .flb files are loaded byte for byte, anything else is assembled
+    let code = if filename.ends_with(".flb") {
+        fs::read(filename).unwrap_or_else(|err| {
+            eprintln!("Error reading file '{}': {}", filename, err);
+            process::exit(1);
+        })
+    } else {
+        let source = fs::read_to_string(filename).unwrap_or_else(|err| {
+            eprintln!("Error reading file '{}': {}", filename, err);
+            process::exit(1);
+        });
+
+        assembler.assemble(&source).unwrap_or_else(|e| {
+            // Print the custom error message from the assembler and exit
+            eprintln!("Assembly Error: {}", e);
+            process::exit(1);
+        })
+    };
+
+    let disassemble_mode = command == "dis" || has_flag("--dis") || has_flag("-d");
+    let bytecode_mode = command == "raw" || has_flag("--raw");
+
+    if disassemble_mode && has_flag("--cfg") {
+        let cfg = ControlFlowGraph::build(&code);
+        print!("{}", cfg.to_dot(&code, Some(assembler.labels())));
+    } else if disassemble_mode && has_flag("--asm") {
+        match disassemble_to_source(&code) {
+            Ok(asm) => print!("{}", asm),
+            Err(e) => {
+                eprintln!("Disassembly Error: {}", e);
+                process::exit(1);
+            }
+        }
+    } else if disassemble_mode && json_mode {
+        print!("{}", disassembly_to_json(&code));
+    } else if disassemble_mode {
+        let dis = disassemble_bytecode(&code);
+        println!("--- DISASSEMBLY (File: {}) ---\n{}", filename, dis);
+    } else if bytecode_mode {
+        println!("--- Raw Bytecode ---");
+        for chunk in code.chunks(10) {
+            for byte in chunk {
+                print!("{:02X} ", byte);
+            }
+            println!();
+        }
+    } else {
+        let overflow = match option_value("--overflow").map(|m| m.as_str()) {
+            None | Some("wrap") => OverflowMode::Wrapping,
+            Some("saturate") => OverflowMode::Saturating,
+            Some("trap") => OverflowMode::Trapping,
+            Some(other) => {
+                eprintln!("Invalid overflow mode '{}', expected wrap, saturate or trap", other);
+                process::exit(1);
+            }
+        };
+        let heap_limit = option_value("--heap-limit").map(|n| n.parse().unwrap_or_else(|_| {
+            eprintln!("Invalid heap limit '{}'", n);
+            process::exit(1);
+        }));
+        let config = VmConfig { overflow, trap_nan: has_flag("--trap-nan"), heap_limit };
+        let mut vm = VirtualMachine::with_config(code, config);
+        if !filename.ends_with(".flb") {
+            vm.debug_info = Some(assembler.debug_info(filename));
+            vm.structs = assembler.structs().to_vec();
+            vm.handlers = assembler.handlers().to_vec();
+        }
+
+        if has_flag("--trace") || option_value("--trace-range").is_some() {
+            let mut tracer = Tracer::new(Box::new(std::io::stderr()));
+            if let Some(range) = option_value("--trace-range") {
+                let bounds = range.split_once(':')
+                    .and_then(|(start, end)| Some(parse_address(start)?..parse_address(end)?));
+                match bounds {
+                    Some(r) => tracer = tracer.with_range(r),
+                    None => {
+                        eprintln!("Invalid trace range '{}', expected <start>:<end>", range);
+                        process::exit(1);
                     }
-                    println!();
                 }
-            } else {
-                // Now 'code' is the unwrapped Vec<u8>
-                let mut vm = VirtualMachine::new(code);
-                vm.execute();
+            }
+            vm.tracer = Some(tracer);
+        }
+
+        // Coverage is computed from the profiler's execution counts
+        let coverage_file = option_value("--coverage");
+        if has_flag("--profile") || coverage_file.is_some() {
+            vm.profiler = Some(Profiler::new());
+        }
 
-                println!("\n--- VM STATE ---");
-                println!("Stack:  {:?}", vm.stack);
-                println!("Memory: {:?}", vm.memory);
+        if let Some(path) = option_value("--resume") {
+            let restored = fs::read(path)
+                .map_err(|err| format!("Error reading snapshot '{}': {}", path, err))
+                .and_then(|data| snapshot::restore(&mut vm, &data));
+            if let Err(e) = restored {
+                eprintln!("{}", e);
+                process::exit(1);
             }
         }
-        Err(e) => {
-            // Print the custom error message from the assembler and exit
-            eprintln!("Assembly Error: {}", e);
+
+        let max_steps = match option_value("--max-steps") {
+            Some(n) => n.parse().unwrap_or_else(|_| {
+                eprintln!("Invalid step count '{}'", n);
+                process::exit(1);
+            }),
+            None => usize::MAX,
+        };
+
+        let result = vm.run_for(max_steps);
+
+        if let Some(path) = option_value("--snapshot")
+            && let Err(err) = fs::write(path, snapshot::save(&vm))
+        {
+            eprintln!("Error writing snapshot '{}': {}", path, err);
+            process::exit(1);
+        }
+
+        if let Some(profiler) = vm.profiler.as_ref().filter(|_| has_flag("--profile")) {
+            eprint!("{}", profiler.report(&vm.code, Some(assembler.labels()), 10));
+        }
+
+        if let (Some(path), Some(profiler)) = (coverage_file, &vm.profiler) {
+            let report = lcov_report(profiler, &vm.code, &assembler.debug_info(filename));
+            if let Err(err) = fs::write(path, report) {
+                eprintln!("Error writing coverage file '{}': {}", path, err);
+                process::exit(1);
+            }
+        }
+
+        if json_mode {
+            print!("{}", vm_state_to_json(&vm));
+        } else {
+            if let Err(e) = &result {
+                eprintln!("{}", e);
+            }
+            println!("\n--- VM STATE ---");
+            println!("Stack:  {:?}", vm.stack);
+            println!("Memory: {:?}", vm.memory);
+            if !vm.heap.is_empty() {
+                let objects: Vec<_> = vm.heap.objects().collect();
+                println!("Heap:   {:?}", objects);
+            }
+            let gc = vm.heap.stats;
+            if gc.collections > 0 {
+                println!(
+                    "GC:     {} collections, {} objects ({} slots) freed, peak {} slots",
+                    gc.collections, gc.objects_freed, gc.slots_freed, gc.peak_slots
+                );
+            }
+        }
+
+        if result.is_err() {
             process::exit(1);
         }
     }
-}
\ No newline at end of file
+}
@@ -1,28 +1,85 @@
-use crate::vm::opcodes::op;
+use crate::vm::debug_info::{DebugEntry, DebugInfo};
+use crate::vm::heap::StructLayout;
+use crate::vm::opcodes::{is_jump, op, relative_jump, relative_jump_info, DISPLACEMENT_WIDTHS};
+use crate::vm::runner::{ExceptionHandler, RoundingMode};
 use std::collections::HashMap;
 
 pub struct Assembler {
     labels: HashMap<String, u32>,
+    /// Memory addresses reserved by `.data` directives
+    data: HashMap<String, u32>,
+    /// Record layouts from `.struct` directives, in declaration order
+    structs: Vec<StructLayout>,
+    /// Exception handler table from `.catch` directives, in declaration order
+    handlers: Vec<ExceptionHandler>,
+    /// Address and 1-based source line of every emitted instruction
+    line_table: Vec<(u32, usize)>,
+    /// Encode jumps to labels PC-relative, in the smallest form that fits
+    relative_jumps: bool,
+}
+
+impl Default for Assembler {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Assembler {
     pub fn new() -> Self {
-        Self { labels: HashMap::new() }
+        Self {
+            labels: HashMap::new(),
+            data: HashMap::new(),
+            structs: Vec::new(),
+            handlers: Vec::new(),
+            line_table: Vec::new(),
+            relative_jumps: false,
+        }
+    }
+
+    /// Makes the assembler encode every absolute jump to a label (`JMP loop`)
+    /// as a PC-relative jump, so the code runs unchanged at any offset. Each
+    /// jump gets the shortest displacement that reaches its target.
+    ///
+    /// Other operands that would embed the absolute address of a label, such
+    /// as TABLESWITCH targets or `ADDR loop`, are rejected in this mode. The
+    /// `.catch` table is not part of the code: its ranges stay offsets from
+    /// the start of the assembled code.
+    pub fn with_relative_jumps(mut self) -> Self {
+        self.relative_jumps = true;
+        self
     }
 
     pub fn assemble(&mut self, input: &str) -> Result<Vec<u8>, String> {
-        let lines: Vec<Vec<&str>> = input
+        // Keep the 1-based line number of every line for the line table
+        let lines: Vec<(usize, Vec<&str>)> = input
             .lines()
-            .map(|l| l.split_whitespace().collect())
-            .filter(|l: &Vec<&str>| !l.is_empty() && !l[0].starts_with(';'))
+            .enumerate()
+            // A token starting with ';' comments out the rest of the line
+            .map(|(n, l)| (n + 1, l.split_whitespace().take_while(|t| !t.starts_with(';')).collect()))
+            .filter(|(_, l): &(usize, Vec<&str>)| !l.is_empty())
             .collect();
+        self.labels.clear();
+        self.line_table.clear();
+        self.data.clear();
+        self.structs.clear();
+        self.handlers.clear();
 
-        // --- PASS 1: Locate Labels ---
+        // --- PASS 1: Locate Labels and Directives ---
         let mut current_address = 0;
-        for line in &lines {
+        let mut data_address = 0;
+        // Encoded size of every line, 0 for lines without an instruction
+        let mut sizes = vec![0; lines.len()];
+        for (i, (_, line)) in lines.iter().enumerate() {
             let first = line[0];
+            if first.starts_with('.') {
+                self.directive(line, &mut data_address)?;
+                continue;
+            }
             let op_idx = if first.ends_with(':') {
                 let label_name = first.trim_end_matches(':').to_string();
+                if self.data.contains_key(&label_name) {
+                    return Err(format!("Duplicate symbol: {}", label_name));
+                }
                 self.labels.insert(label_name, current_address);
                 1 
             } else { 
@@ -30,24 +87,80 @@ impl Assembler {
             };
             
             if op_idx < line.len() {
-                let size = self.get_instruction_size(line[op_idx]);
+                let mut size = self.get_instruction_size(line[op_idx]);
                 if size == 0 {
                     return Err(format!("Unknown instruction: {}", line[op_idx]));
                 }
+                // Every TABLESWITCH argument after the low bound and default is a case
+                if op::from_mnemonic(line[op_idx]) == Some(op::TABLESWITCH) {
+                    size += 4 * line.len().saturating_sub(op_idx + 3) as u32;
+                }
+                sizes[i] = size;
                 current_address += size;
             }
         }
 
+        // --- Branch relaxation: jumps to labels start at the shortest relative
+        // form and are widened until every displacement fits ---
+        let relaxed: HashMap<usize, &str> = lines.iter().enumerate().filter_map(|(i, (_, line))| {
+            let op_idx = if line[0].ends_with(':') { 1 } else { 0 };
+            let opcode = op::from_mnemonic(line.get(op_idx)?)?;
+            let target = *line.get(op_idx + 1)?;
+            let relaxable = self.relative_jumps && is_jump(opcode) && relative_jump_info(opcode).is_none();
+            (relaxable && self.labels.contains_key(target)).then_some((i, target))
+        }).collect();
+        for &i in relaxed.keys() {
+            sizes[i] = 1 + DISPLACEMENT_WIDTHS[0];
+        }
+        while !relaxed.is_empty() {
+            let addresses = self.place_labels(&lines, &sizes);
+            let mut widened = false;
+            for (&i, target) in &relaxed {
+                let size = 1 + displacement_width(self.labels[*target] as i64 - addresses[i] as i64);
+                if size > sizes[i] {
+                    sizes[i] = size;
+                    widened = true;
+                }
+            }
+            if !widened {
+                break;
+            }
+        }
+
+        // Handler ranges refer to labels, so they are resolved once the layout is final
+        for (_, line) in lines.iter().filter(|(_, line)| line[0] == ".catch") {
+            let handler = self.catch_handler(line)?;
+            // Ranges must nest, so the innermost one covering an address is unique
+            let crossing = self.handlers.iter().find(|h| {
+                h.start < handler.end && handler.start < h.end
+                    && !(h.start <= handler.start && handler.end <= h.end)
+                    && !(handler.start <= h.start && h.end <= handler.end)
+            });
+            if let Some(other) = crossing {
+                return Err(format!(
+                    "Overlapping .catch ranges {}..{} and {}..{}",
+                    other.start, other.end, handler.start, handler.end
+                ));
+            }
+            self.handlers.push(handler);
+        }
+
         // --- PASS 2: Generate Bytes ---
         let mut bytecode = Vec::new();
-        for line in &lines {
+        for (i, (line_number, line)) in lines.iter().enumerate() {
+            if line[0].starts_with('.') { continue; }
             let op_idx = if line[0].ends_with(':') { 1 } else { 0 };
             if op_idx >= line.len() { continue; }
 
+            self.line_table.push((bytecode.len() as u32, *line_number));
+
             let mnemonic = line[op_idx];
-            let opcode = op::from_mnemonic(mnemonic)
+            let mut opcode = op::from_mnemonic(mnemonic)
                 .ok_or_else(|| format!("Unknown mnemonic: {}", mnemonic))?;
-            
+            if relaxed.contains_key(&i) {
+                opcode = relative_jump(opcode, sizes[i] - 1).unwrap();
+            }
+
             let info = op::get_info(opcode).unwrap(); // Safe because from_mnemonic succeeded
             bytecode.push(opcode);
 
@@ -55,29 +168,73 @@ impl Assembler {
                 if line.len() <= op_idx + 1 {
                     return Err(format!("Missing argument for {}", mnemonic));
                 }
+                if opcode == op::TABLESWITCH {
+                    self.encode_table(&mut bytecode, &line[op_idx + 1..])?;
+                    continue;
+                }
                 let arg = line[op_idx + 1];
-                self.encode_operand(&mut bytecode, arg, info.size)?;
+                self.encode_operand(&mut bytecode, opcode, arg, info.size)?;
             }
         }
         Ok(bytecode)
     }
 
-    fn encode_operand(&self, bytecode: &mut Vec<u8>, arg: &str, size: u32) -> Result<(), String> {
+    fn encode_operand(&self, bytecode: &mut Vec<u8>, opcode: u8, arg: &str, size: u32) -> Result<(), String> {
+        if relative_jump_info(opcode).is_some() {
+            // A label is turned into its displacement from the jump, a number is the displacement itself
+            let addr = bytecode.len() as i64 - 1;
+            let offset = match self.labels.get(arg) {
+                Some(&target) => target as i64 - addr,
+                None => arg.parse::<i32>().map_err(|_| format!("Invalid i32 or label: {}", arg))? as i64,
+            };
+            if displacement_width(offset) > size - 1 {
+                let name = op::get_info(opcode).unwrap().name;
+                return Err(format!("Jump displacement {} out of range for {}", offset, name));
+            }
+            let bytes = (offset as i32).to_be_bytes();
+            bytecode.extend(&bytes[bytes.len() - (size as usize - 1)..]);
+            return Ok(());
+        }
+
         match size {
+            2 if matches!(opcode, op::F2I | op::F2L) => { // Rounding mode, by name or number
+                let mode = RoundingMode::from_name(arg)
+                    .map(|m| m as u8)
+                    .or_else(|| arg.parse::<u8>().ok().filter(|&b| RoundingMode::from_byte(b).is_some()))
+                    .ok_or_else(|| format!("Invalid rounding mode: {}", arg))?;
+                bytecode.push(mode);
+            }
             2 => { // 1-byte operand (BIPUSH)
                 let val = arg.parse::<u8>().map_err(|_| format!("Invalid u8: {}", arg))?;
                 bytecode.push(val);
             }
-            5 => { // 4-byte operand (IPUSH, Jumps, Load/Store)
-                let val = if let Some(&addr) = self.labels.get(arg) {
-                    addr
-                } else {
-                    arg.parse::<i32>()
-                        .map(|v| v as u32)
-                        .map_err(|_| format!("Invalid i32 or label: {}", arg))?
+            5 if opcode == op::NEW => { // Struct index, by name or number
+                let val = match self.structs.iter().position(|s| s.name == arg) {
+                    Some(index) => index as u32,
+                    None => arg.parse::<u32>().map_err(|_| format!("Unknown struct: {}", arg))?,
+                };
+                bytecode.extend(&val.to_be_bytes());
+            }
+            5 if matches!(opcode, op::GETFIELD | op::SETFIELD) => { // Field offset, as Struct.field or a number
+                let val = match arg.split_once('.') {
+                    Some((name, field)) => {
+                        let layout = self.structs.iter().find(|s| s.name == name)
+                            .ok_or_else(|| format!("Unknown struct: {}", name))?;
+                        layout.fields.iter().position(|f| f == field)
+                            .ok_or_else(|| format!("Unknown field {} in struct {}", field, name))? as u32
+                    }
+                    None => arg.parse::<u32>().map_err(|_| format!("Invalid field: {}", arg))?,
                 };
                 bytecode.extend(&val.to_be_bytes());
             }
+            5 => { // 4-byte operand (IPUSH, Jumps, Load/Store)
+                let val = self.absolute_address(arg)?;
+                bytecode.extend(&val.to_be_bytes());
+            }
+            9 if opcode == op::LPUSH => { // 8-byte integer operand
+                let val = arg.parse::<i64>().map_err(|_| format!("Invalid i64: {}", arg))?;
+                bytecode.extend(&val.to_be_bytes());
+            }
             9 => { // 8-byte operand (FPUSH)
                 let val = arg.parse::<f64>().map_err(|_| format!("Invalid f64: {}", arg))?;
                 bytecode.extend(&val.to_be_bytes());
@@ -87,14 +244,177 @@ impl Assembler {
         Ok(())
     }
 
+    /// Moves every label to the address given by the instruction sizes and
+    /// returns the address of each line.
+    fn place_labels(&mut self, lines: &[(usize, Vec<&str>)], sizes: &[u32]) -> Vec<u32> {
+        let mut address = 0;
+        let mut addresses = Vec::with_capacity(lines.len());
+        for ((_, line), size) in lines.iter().zip(sizes) {
+            if line[0].ends_with(':') {
+                self.labels.insert(line[0].trim_end_matches(':').to_string(), address);
+            }
+            addresses.push(address);
+            address += size;
+        }
+        addresses
+    }
+
+    /// Builds the handler table entry of a `.catch` directive
+    fn catch_handler(&self, line: &[&str]) -> Result<ExceptionHandler, String> {
+        let depth = match line.get(4) {
+            Some(depth) => depth.parse().map_err(|_| format!("Invalid stack depth for .catch: {}", depth))?,
+            None => 0,
+        };
+        Ok(ExceptionHandler {
+            start: self.address(line[1])? as usize,
+            end: self.address(line[2])? as usize,
+            handler: self.address(line[3])? as usize,
+            depth,
+        })
+    }
+
+    /// Resolves a label, a `.data` symbol or a literal i32 or u32
+    fn address(&self, arg: &str) -> Result<u32, String> {
+        match self.labels.get(arg).or_else(|| self.data.get(arg)) {
+            Some(&addr) => Ok(addr),
+            None => arg.parse::<i32>()
+                .map(|v| v as u32)
+                .or_else(|_| arg.parse::<u32>())
+                .map_err(|_| format!("Invalid i32 or label: {}", arg)),
+        }
+    }
+
+    /// Like `address`, but with relative jumps refuses code labels, whose
+    /// absolute address would be wrong once the code is moved
+    fn absolute_address(&self, arg: &str) -> Result<u32, String> {
+        if self.relative_jumps && self.labels.contains_key(arg) {
+            return Err(format!("Label {} cannot be used as an absolute address with relative jumps", arg));
+        }
+        self.address(arg)
+    }
+
+    /// Encodes `TABLESWITCH low default case...`, where case `i` is the
+    /// target for selector `low + i`.
+    fn encode_table(&self, bytecode: &mut Vec<u8>, args: &[&str]) -> Result<(), String> {
+        if args.len() < 2 {
+            return Err("Missing default target for TABLESWITCH".to_string());
+        }
+        let low = args[0].parse::<i32>().map_err(|_| format!("Invalid i32: {}", args[0]))?;
+        bytecode.extend(&low.to_be_bytes());
+        bytecode.extend(&self.absolute_address(args[1])?.to_be_bytes());
+        bytecode.extend(&(args.len() as u32 - 2).to_be_bytes());
+        for case in &args[2..] {
+            bytecode.extend(&self.absolute_address(case)?.to_be_bytes());
+        }
+        Ok(())
+    }
+
+    /// Handles a directive line during pass 1.
+    ///
+    /// `.data name [slots]` reserves `slots` (default 1) consecutive memory
+    /// addresses and makes `name` usable wherever an address operand is expected.
+    ///
+    /// `.struct Name field...` declares a record layout for NEW, with fields
+    /// referred to as `Name.field` by GETFIELD and SETFIELD.
+    ///
+    /// `.catch start end handler [depth]` protects the instructions from
+    /// `start` up to `end` with the handler at `handler`, which runs with the
+    /// stack cut back to `depth` (default 0) values plus the exception.
+    /// Ranges may nest but not partially overlap; the innermost one wins.
+    fn directive(&mut self, line: &[&str], data_address: &mut u32) -> Result<(), String> {
+        match line[0] {
+            ".data" => {
+                let name = line.get(1).ok_or_else(|| "Missing name for .data".to_string())?;
+                let slots = match line.get(2) {
+                    Some(n) => n.parse::<u32>().ok().filter(|&n| n > 0)
+                        .ok_or_else(|| format!("Invalid slot count for .data {}: {}", name, n))?,
+                    None => 1,
+                };
+                if self.labels.contains_key(*name) || self.data.contains_key(*name) {
+                    return Err(format!("Duplicate symbol: {}", name));
+                }
+                self.data.insert(name.to_string(), *data_address);
+                *data_address += slots;
+                Ok(())
+            }
+            ".struct" => {
+                let name = line.get(1).ok_or_else(|| "Missing name for .struct".to_string())?;
+                if self.structs.iter().any(|s| s.name == *name) {
+                    return Err(format!("Duplicate struct: {}", name));
+                }
+                let mut fields: Vec<String> = Vec::new();
+                for field in &line[2..] {
+                    if fields.iter().any(|f| f == field) {
+                        return Err(format!("Duplicate field {} in struct {}", field, name));
+                    }
+                    fields.push(field.to_string());
+                }
+                self.structs.push(StructLayout { name: name.to_string(), fields });
+                Ok(())
+            }
+            ".catch" => match line.len() {
+                4 | 5 => Ok(()), // Resolved after the labels are placed
+                _ => Err("Expected .catch <start> <end> <handler> [depth]".to_string()),
+            },
+            other => Err(format!("Unknown directive: {}", other)),
+        }
+    }
+
+    /// Returns the memory address of every `.data` symbol from the last call to `assemble`.
+    pub fn data_symbols(&self) -> &HashMap<String, u32> {
+        &self.data
+    }
+
+    /// Returns the `.struct` layouts from the last call to `assemble`, indexed by NEW.
+    pub fn structs(&self) -> &[StructLayout] {
+        &self.structs
+    }
+
+    /// Returns the exception handler table from the last call to `assemble`.
+    pub fn handlers(&self) -> &[ExceptionHandler] {
+        &self.handlers
+    }
+
+    /// Returns the symbol table built by the last call to `assemble`.
+    pub fn labels(&self) -> &HashMap<String, u32> {
+        &self.labels
+    }
+
+    /// Builds the debug table for the last call to `assemble`, attributing
+    /// every instruction to `file`, its line and the closest preceding label.
+    pub fn debug_info(&self, file: &str) -> DebugInfo {
+        let mut labels: Vec<(u32, &String)> = self.labels.iter().map(|(name, &addr)| (addr, name)).collect();
+        labels.sort();
+
+        let entries = self.line_table.iter().map(|&(addr, line)| {
+            // Labels are sorted by address then name, so take the first one of the last address
+            let enclosing = labels.iter().rev().find(|(a, _)| *a <= addr);
+            let label = enclosing.and_then(|(a, _)| labels.iter().find(|(b, _)| b == a));
+            DebugEntry { addr, line, label: label.map(|(_, name)| name.to_string()) }
+        }).collect();
+
+        DebugInfo { file: file.to_string(), entries }
+    }
+
     pub fn get_instruction_size(&self, mnemonic: &str) -> u32 {
         op::from_mnemonic(mnemonic)
-            .and_then(|code| op::get_info(code))
+            .and_then(op::get_info)
             .map(|info| info.size)
             .unwrap_or(0)
     }
 }
 
+/// Smallest displacement width, in bytes, that holds `offset`
+fn displacement_width(offset: i64) -> u32 {
+    if i8::try_from(offset).is_ok() {
+        1
+    } else if i16::try_from(offset).is_ok() {
+        2
+    } else {
+        4
+    }
+}
+
 
 #[cfg(test)]
 mod test_assembler {
@@ -213,6 +533,210 @@ mod test_assembler {
         assert_eq!(val, 123.456);
     }
 
+    #[test]
+    fn test_assemble_lpush() {
+        let mut assembler = Assembler::new();
+        let bytecode = assembler.assemble("LPUSH -9000000000").expect("Assembly failed");
+
+        assert_eq!(bytecode[0], op::LPUSH);
+        let val = i64::from_be_bytes(bytecode[1..9].try_into().unwrap());
+        assert_eq!(val, -9_000_000_000);
+        assert!(assembler.assemble("LPUSH 1.5").is_err());
+    }
+
+    #[test]
+    fn test_assemble_rounding_modes() {
+        let mut assembler = Assembler::new();
+        let bytecode = assembler.assemble("F2I trunc\nF2I FLOOR\nF2L ceil\nF2L 3").expect("Assembly failed");
+
+        assert_eq!(bytecode, vec![op::F2I, 0, op::F2I, 1, op::F2L, 2, op::F2L, 3]);
+        assert!(assembler.assemble("F2I nearest").is_err());
+        assert!(assembler.assemble("F2I 4").is_err());
+    }
+
+    #[test]
+    fn test_assemble_data_directive() {
+        let mut assembler = Assembler::new();
+        let source = "
+            .data count
+            .data buffer 4
+            .data total
+            ADDR buffer
+            STORE total
+            LOAD count
+        ";
+        let bytecode = assembler.assemble(source).expect("Assembly failed");
+
+        assert_eq!(assembler.data_symbols()["count"], 0);
+        assert_eq!(assembler.data_symbols()["buffer"], 1);
+        assert_eq!(assembler.data_symbols()["total"], 5);
+        assert_eq!(bytecode, vec![op::ADDR, 0, 0, 0, 1, op::STORE, 0, 0, 0, 5, op::LOAD, 0, 0, 0, 0]);
+        assert_eq!(assembler.debug_info("prog.flint").entries[0].line, 5);
+    }
+
+    #[test]
+    fn test_assemble_data_ignores_trailing_comment() {
+        let mut assembler = Assembler::new();
+        let bytecode = assembler.assemble(".data x ; counter\n.data buf 2 ; pair\nLOAD buf").expect("Assembly failed");
+
+        assert_eq!(assembler.data_symbols()["x"], 0);
+        assert_eq!(assembler.data_symbols()["buf"], 1);
+        assert_eq!(bytecode, vec![op::LOAD, 0, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_assemble_directive_errors() {
+        let mut assembler = Assembler::new();
+        assert_eq!(assembler.assemble(".data"), Err("Missing name for .data".to_string()));
+        assert_eq!(assembler.assemble(".data x 0"), Err("Invalid slot count for .data x: 0".to_string()));
+        assert_eq!(assembler.assemble(".data x\n.data x"), Err("Duplicate symbol: x".to_string()));
+        assert_eq!(assembler.assemble(".data x\nx:\nHALT"), Err("Duplicate symbol: x".to_string()));
+        assert_eq!(assembler.assemble(".text"), Err("Unknown directive: .text".to_string()));
+    }
+
+    #[test]
+    fn test_assemble_struct_directive() {
+        let mut assembler = Assembler::new();
+        let input = "
+            .struct Point x y
+            .struct Node value next
+            NEW Node
+            DUP
+            BIPUSH 7
+            SETFIELD Node.value
+            GETFIELD Node.next
+            NEW 0
+            HALT
+        ";
+        let bytecode = assembler.assemble(input).expect("Assembly failed");
+        assert_eq!(bytecode, vec![
+            op::NEW, 0, 0, 0, 1,
+            op::DUP,
+            op::BIPUSH, 7,
+            op::SETFIELD, 0, 0, 0, 0,
+            op::GETFIELD, 0, 0, 0, 1,
+            op::NEW, 0, 0, 0, 0,
+            op::HALT,
+        ]);
+        assert_eq!(assembler.structs()[0], StructLayout { name: "Point".to_string(), fields: vec!["x".to_string(), "y".to_string()] });
+    }
+
+    #[test]
+    fn test_assemble_struct_ignores_trailing_comment() {
+        let mut assembler = Assembler::new();
+        assembler.assemble(".struct Point x y ; a point\n.struct Empty ;no fields").expect("Assembly failed");
+
+        assert_eq!(assembler.structs(), &[
+            StructLayout { name: "Point".to_string(), fields: vec!["x".to_string(), "y".to_string()] },
+            StructLayout { name: "Empty".to_string(), fields: vec![] },
+        ]);
+    }
+
+    #[test]
+    fn test_assemble_struct_errors() {
+        let mut assembler = Assembler::new();
+        assert_eq!(assembler.assemble(".struct"), Err("Missing name for .struct".to_string()));
+        assert_eq!(assembler.assemble(".struct P x\n.struct P y"), Err("Duplicate struct: P".to_string()));
+        assert_eq!(assembler.assemble(".struct P x x"), Err("Duplicate field x in struct P".to_string()));
+        assert_eq!(assembler.assemble("NEW Q"), Err("Unknown struct: Q".to_string()));
+        assert_eq!(assembler.assemble(".struct P x\nGETFIELD P.z"), Err("Unknown field z in struct P".to_string()));
+        assert_eq!(assembler.assemble("SETFIELD Q.x"), Err("Unknown struct: Q".to_string()));
+    }
+
+    #[test]
+    fn test_assemble_tableswitch() {
+        let mut assembler = Assembler::new();
+        let input = "
+            TABLESWITCH -1 done a b
+            a:
+            NOP
+            b:
+            ADDR done
+            JMPI
+            done:
+            HALT
+        ";
+        let bytecode = assembler.assemble(input).expect("Assembly failed");
+
+        let mut expected = vec![op::TABLESWITCH];
+        expected.extend(&(-1i32).to_be_bytes());
+        expected.extend(&28u32.to_be_bytes());
+        expected.extend(&2u32.to_be_bytes());
+        expected.extend(&21u32.to_be_bytes());
+        expected.extend(&22u32.to_be_bytes());
+        expected.extend([op::NOP, op::ADDR, 0, 0, 0, 28, op::JMPI, op::HALT]);
+        assert_eq!(bytecode, expected);
+
+        assert_eq!(assembler.assemble("TABLESWITCH 0"), Err("Missing default target for TABLESWITCH".to_string()));
+        assert_eq!(assembler.assemble("TABLESWITCH x end\nend:"), Err("Invalid i32: x".to_string()));
+        assert_eq!(assembler.assemble("TABLESWITCH 0 end nowhere\nend:"), Err("Invalid i32 or label: nowhere".to_string()));
+    }
+
+    #[test]
+    fn test_assemble_tableswitch_ignores_trailing_comment() {
+        let mut assembler = Assembler::new();
+        let bytecode = assembler.assemble("TABLESWITCH 0 done a ; jump table\na:\nNOP\ndone:\nHALT").expect("Assembly failed");
+
+        // Both passes see a single case, so the labels follow a 17-byte switch
+        let mut expected = vec![op::TABLESWITCH];
+        expected.extend(&0i32.to_be_bytes());
+        expected.extend(&18u32.to_be_bytes());
+        expected.extend(&1u32.to_be_bytes());
+        expected.extend(&17u32.to_be_bytes());
+        expected.extend([op::NOP, op::HALT]);
+        assert_eq!(bytecode, expected);
+    }
+
+    #[test]
+    fn test_assemble_catch_directive() {
+        let mut assembler = Assembler::new().with_relative_jumps();
+        let input = "
+            .catch body end handler
+            body:
+            BIPUSH 1
+            JMP end
+            .catch inner end 0 2
+            inner:
+            THROW
+            end:
+            HALT
+            handler:
+            POP
+        ";
+        assembler.assemble(input).expect("Assembly failed");
+
+        // The table uses the addresses after relaxation shortened the JMP
+        assert_eq!(assembler.handlers(), &[
+            ExceptionHandler { start: 0, end: 5, handler: 6, depth: 0 },
+            ExceptionHandler { start: 4, end: 5, handler: 0, depth: 2 },
+        ]);
+    }
+
+    #[test]
+    fn test_assemble_catch_errors() {
+        let mut assembler = Assembler::new();
+        let usage = Err("Expected .catch <start> <end> <handler> [depth]".to_string());
+        assert_eq!(assembler.assemble(".catch a b"), usage);
+        assert_eq!(assembler.assemble(".catch a b c d e"), usage);
+        assert_eq!(assembler.assemble(".catch 0 1 2 x"), Err("Invalid stack depth for .catch: x".to_string()));
+        assert_eq!(assembler.assemble(".catch 0 1 nowhere"), Err("Invalid i32 or label: nowhere".to_string()));
+        assert_eq!(
+            assembler.assemble(".catch 0 4 9\n.catch 2 6 9"),
+            Err("Overlapping .catch ranges 0..4 and 2..6".to_string())
+        );
+    }
+
+    #[test]
+    fn test_assemble_catch_ignores_trailing_comment() {
+        let mut assembler = Assembler::new();
+        assembler.assemble(".catch a b h ; protect\n.catch a b h 1 ;depth\na:\nNOP\nb:\nh:\nHALT").expect("Assembly failed");
+
+        assert_eq!(assembler.handlers(), &[
+            ExceptionHandler { start: 0, end: 1, handler: 1, depth: 0 },
+            ExceptionHandler { start: 0, end: 1, handler: 1, depth: 1 },
+        ]);
+    }
+
     #[test]
     fn test_assemble_store_load() {
         let mut assembler = Assembler::new();
@@ -259,4 +783,93 @@ mod test_assembler {
         let addr = u32::from_be_bytes([bytecode[1], bytecode[2], bytecode[3], bytecode[4]]);
         assert_eq!(addr, 7);
     }
+
+    #[test]
+    fn test_relative_jumps_use_shortest_form() {
+        let mut assembler = Assembler::new().with_relative_jumps();
+        let bytecode = assembler.assemble("loop:\nNOP\nJNE loop\nJMP 0\nHALT").expect("Assembly failed");
+
+        // Jumps to a numeric address stay absolute
+        assert_eq!(bytecode, vec![op::NOP, op::JNE8, 0xFF, op::JMP, 0, 0, 0, 0, op::HALT]);
+    }
+
+    #[test]
+    fn test_relative_jumps_widen_until_they_fit() {
+        let mut assembler = Assembler::new().with_relative_jumps();
+        // The second jump needs two bytes, which pushes the first one out of i8 range
+        let source = format!("JMP a\n{}JMP b\na:\n{}b:\nJMP a", "NOP\n".repeat(123), "NOP\n".repeat(200));
+        let bytecode = assembler.assemble(&source).expect("Assembly failed");
+
+        assert_eq!(bytecode[..3], [op::JMP16, 0, 129]);
+        assert_eq!(bytecode[126..129], [op::JMP16, 0, 203]);
+        assert_eq!(assembler.labels()["a"], 129);
+        assert_eq!(assembler.labels()["b"], 329);
+        assert_eq!(bytecode[329..], [op::JMP16, 0xFF, 0x38]); // -200
+        assert_eq!(assembler.debug_info("prog.flint").entries.last().unwrap().addr, 329);
+    }
+
+    #[test]
+    fn test_relative_jumps_reject_absolute_label_addresses() {
+        let mut assembler = Assembler::new().with_relative_jumps();
+        let error = |label: &str| Err(format!("Label {} cannot be used as an absolute address with relative jumps", label));
+        assert_eq!(assembler.assemble("TABLESWITCH 0 end\nend:\nHALT"), error("end"));
+        assert_eq!(assembler.assemble("TABLESWITCH 0 0 a\na:\nHALT"), error("a"));
+        assert_eq!(assembler.assemble("start:\nADDR start\nJMPI"), error("start"));
+
+        // Data symbols and numeric addresses do not depend on where the code is
+        let bytecode = assembler.assemble(".data x\nADDR x\nTABLESWITCH 0 6 6").expect("Assembly failed");
+        assert_eq!(bytecode[..5], [op::ADDR, 0, 0, 0, 0]);
+
+        // .catch ranges stay offsets from the start of the code
+        assembler.assemble(".catch body end end\nNOP\nbody:\nJMP end\nend:\nHALT").expect("Assembly failed");
+        assert_eq!(assembler.handlers(), &[ExceptionHandler { start: 1, end: 3, handler: 3, depth: 0 }]);
+    }
+
+    #[test]
+    fn test_assemble_explicit_relative_jumps() {
+        let mut assembler = Assembler::new();
+        let bytecode = assembler.assemble("JMP16 end\nJT8 -3\nend:\nJU32 end").expect("Assembly failed");
+        assert_eq!(bytecode, vec![op::JMP16, 0, 5, op::JT8, 0xFD, op::JU32, 0, 0, 0, 0]);
+
+        let source = format!("JMP8 end\n{}end:", "NOP\n".repeat(126));
+        assert_eq!(assembler.assemble(&source), Err("Jump displacement 128 out of range for JMP8".to_string()));
+        assert_eq!(assembler.assemble("JMP16 40000"), Err("Jump displacement 40000 out of range for JMP16".to_string()));
+    }
+
+    #[test]
+    fn test_assemble_bitwise_instructions() {
+        let mut assembler = Assembler::new();
+        let bytecode = assembler.assemble("AND\nor\nXOR\nNOT\nSHL\nSHR\nUSHR").expect("Assembly failed");
+
+        assert_eq!(bytecode, vec![op::AND, op::OR, op::XOR, op::NOT, op::SHL, op::SHR, op::USHR]);
+    }
+
+    #[test]
+    fn test_assemble_records_source_lines() {
+        let mut assembler = Assembler::new();
+        let input = "; header\nBIPUSH 1\n\nloop:\n  DUP ; comment\nend: HALT";
+        assembler.assemble(input).expect("Assembly failed");
+
+        let lines: Vec<(u32, usize)> = assembler.debug_info("prog.flint").entries.iter()
+            .map(|entry| (entry.addr, entry.line))
+            .collect();
+        assert_eq!(lines, vec![(0, 2), (2, 5), (3, 6)]);
+    }
+
+    #[test]
+    fn test_assemble_debug_info_attributes_labels() {
+        let mut assembler = Assembler::new();
+        // Both "a" and "b" name the HALT, the alphabetically first one wins
+        let input = "BIPUSH 1\nloop:\nDUP\nPOP\nb:\na:\nHALT";
+        assembler.assemble(input).expect("Assembly failed");
+
+        let info = assembler.debug_info("prog.flint");
+        assert_eq!(info.file, "prog.flint");
+        assert_eq!(info.entries, vec![
+            DebugEntry { addr: 0, line: 1, label: None },
+            DebugEntry { addr: 2, line: 3, label: Some("loop".to_string()) },
+            DebugEntry { addr: 3, line: 4, label: Some("loop".to_string()) },
+            DebugEntry { addr: 4, line: 7, label: Some("a".to_string()) },
+        ]);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,291 @@
+use crate::vm::decoder::{decode, DecodeError, Instruction};
+use crate::vm::disassembler::{format_instruction, label_names};
+use crate::vm::opcodes::{ends_flow, is_jump};
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Execution continues with the next instruction in memory.
+    Fallthrough,
+    /// A jump transfers control to its target address.
+    Taken,
+}
+
+/// An edge between two basic blocks, identified by their start addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+/// A straight-line run of instructions with a single entry and a single exit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// Address one past the last byte of the block.
+    pub end: usize,
+    /// Start address of every instruction in the block.
+    pub instructions: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+/// Address span of a decoded entry. Unknown bytes are kept as one-byte
+/// instructions so that addresses stay in sync.
+fn span(entry: &Result<Instruction, DecodeError>, len: usize) -> (usize, usize) {
+    match *entry {
+        Ok(ins) => (ins.addr, ins.next_addr()),
+        Err(DecodeError::UnknownOpcode { addr, .. }) => (addr, addr + 1),
+        Err(DecodeError::Truncated { addr, .. }) => (addr, len),
+    }
+}
+
+impl ControlFlowGraph {
+    /// Splits the bytecode into basic blocks at jump targets and after every
+    /// branch or HALT, then links the blocks with fallthrough and taken edges.
+    pub fn build(bytecode: &[u8]) -> Self {
+        let entries: Vec<Result<Instruction, DecodeError>> = decode(bytecode).collect();
+        let starts: BTreeSet<usize> = entries.iter().map(|e| span(e, bytecode.len()).0).collect();
+
+        let mut leaders = BTreeSet::new();
+        if !entries.is_empty() {
+            leaders.insert(0);
+        }
+        for ins in entries.iter().flatten() {
+            // Targets that land inside an instruction or past the end cannot start a block
+            leaders.extend(ins.targets(bytecode).into_iter().filter(|t| starts.contains(t)));
+            if (is_jump(ins.opcode) || ends_flow(ins.opcode)) && starts.contains(&ins.next_addr()) {
+                leaders.insert(ins.next_addr());
+            }
+        }
+
+        let mut blocks: Vec<BasicBlock> = Vec::new();
+        for entry in &entries {
+            let (addr, next) = span(entry, bytecode.len());
+            if leaders.contains(&addr) {
+                blocks.push(BasicBlock { start: addr, end: addr, instructions: Vec::new() });
+            }
+            let block = blocks.last_mut().unwrap();
+            block.instructions.push(addr);
+            block.end = next;
+        }
+
+        let by_addr: HashMap<usize, &Result<Instruction, DecodeError>> =
+            entries.iter().map(|e| (span(e, bytecode.len()).0, e)).collect();
+        let mut edges = Vec::new();
+        for block in &blocks {
+            let last = match block.instructions.last().and_then(|addr| by_addr.get(addr)) {
+                Some(&last) => last,
+                None => continue,
+            };
+
+            if let Ok(ins) = last {
+                let targets: BTreeSet<usize> = ins.targets(bytecode).into_iter().filter(|t| leaders.contains(t)).collect();
+                edges.extend(targets.into_iter().map(|to| Edge { from: block.start, to, kind: EdgeKind::Taken }));
+            }
+
+            // The target of JMPI is only known at run time, so it has no edges
+            let falls_through = match last {
+                Ok(ins) => !ends_flow(ins.opcode),
+                Err(_) => true,
+            };
+            if falls_through && leaders.contains(&block.end) {
+                edges.push(Edge { from: block.start, to: block.end, kind: EdgeKind::Fallthrough });
+            }
+        }
+
+        Self { blocks, edges }
+    }
+
+    /// Renders the graph as Graphviz DOT text. When a symbol table is given,
+    /// blocks are headed by their label and jump operands use label names.
+    pub fn to_dot(&self, bytecode: &[u8], labels: Option<&HashMap<String, u32>>) -> String {
+        let names = label_names(labels);
+        let mut dot = String::from("digraph cfg {\n");
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+
+        for block in &self.blocks {
+            let mut text = String::new();
+            if let Some(name) = names.get(&block.start) {
+                text.push_str(&format!("{}:\\l", escape(name)));
+            }
+            for &addr in &block.instructions {
+                let line = format_instruction(bytecode, addr, &names);
+                text.push_str(&format!("{:04X}: {}\\l", addr, escape(&line)));
+            }
+            dot.push_str(&format!("    block_{:04X} [label=\"{}\"];\n", block.start, text));
+        }
+
+        for edge in &self.edges {
+            let attrs = match edge.kind {
+                EdgeKind::Taken => "label=\"taken\"",
+                EdgeKind::Fallthrough => "label=\"fallthrough\", style=dashed",
+            };
+            dot.push_str(&format!("    block_{:04X} -> block_{:04X} [{}];\n", edge.from, edge.to, attrs));
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+
+#[cfg(test)]
+mod test_cfg {
+    use super::*;
+    use crate::vm::assembler::Assembler;
+    use crate::vm::opcodes::op;
+
+    fn assemble(source: &str) -> (Vec<u8>, HashMap<String, u32>) {
+        let mut assembler = Assembler::new();
+        let code = assembler.assemble(source).expect("Assembly failed");
+        (code, assembler.labels().clone())
+    }
+
+    #[test]
+    fn test_straight_line_code_is_one_block() {
+        let (code, _) = assemble("BIPUSH 1\nBIPUSH 2\nADD\nHALT");
+        let cfg = ControlFlowGraph::build(&code);
+
+        assert_eq!(cfg.blocks.len(), 1);
+        assert_eq!(cfg.blocks[0].instructions, vec![0, 2, 4, 5]);
+        assert!(cfg.edges.is_empty());
+    }
+
+    #[test]
+    fn test_loop_splits_at_target_and_after_branch() {
+        let (code, _) = assemble("
+            BIPUSH 10
+            loop:
+            BIPUSH 1
+            SUB
+            DUP
+            BIPUSH 0
+            CMP
+            JG loop
+            HALT
+        ");
+        let cfg = ControlFlowGraph::build(&code);
+
+        let starts: Vec<usize> = cfg.blocks.iter().map(|b| b.start).collect();
+        assert_eq!(starts, vec![0, 2, 14]);
+        assert!(cfg.edges.contains(&Edge { from: 0, to: 2, kind: EdgeKind::Fallthrough }));
+        assert!(cfg.edges.contains(&Edge { from: 2, to: 2, kind: EdgeKind::Taken }));
+        assert!(cfg.edges.contains(&Edge { from: 2, to: 14, kind: EdgeKind::Fallthrough }));
+        assert_eq!(cfg.edges.len(), 3);
+    }
+
+    #[test]
+    fn test_unconditional_jump_has_no_fallthrough() {
+        let (code, _) = assemble("
+            JMP end
+            BIPUSH 99
+            end:
+            HALT
+        ");
+        let cfg = ControlFlowGraph::build(&code);
+
+        // The skipped BIPUSH still falls through to the target, but JMP itself does not
+        assert_eq!(cfg.blocks.len(), 3);
+        assert_eq!(cfg.edges, vec![
+            Edge { from: 0, to: 7, kind: EdgeKind::Taken },
+            Edge { from: 5, to: 7, kind: EdgeKind::Fallthrough },
+        ]);
+    }
+
+    #[test]
+    fn test_tableswitch_links_every_case() {
+        let (code, _) = assemble("
+            TABLESWITCH 0 other zero one zero
+            zero:
+            HALT
+            one:
+            JMPI
+            other:
+            HALT
+        ");
+        let cfg = ControlFlowGraph::build(&code);
+
+        // Duplicate cases share an edge, and neither TABLESWITCH nor JMPI falls through
+        assert_eq!(cfg.blocks.len(), 4);
+        assert_eq!(cfg.edges, vec![
+            Edge { from: 0, to: 25, kind: EdgeKind::Taken },
+            Edge { from: 0, to: 26, kind: EdgeKind::Taken },
+            Edge { from: 0, to: 27, kind: EdgeKind::Taken },
+        ]);
+    }
+
+    #[test]
+    fn test_throw_has_no_fallthrough() {
+        let (code, _) = assemble("
+            BIPUSH 1
+            THROW
+            BIPUSH 2
+            HALT
+        ");
+        let cfg = ControlFlowGraph::build(&code);
+
+        // Handlers are looked up at run time, so THROW has no edges at all
+        let starts: Vec<usize> = cfg.blocks.iter().map(|b| b.start).collect();
+        assert_eq!(starts, vec![0, 3]);
+        assert!(cfg.edges.is_empty());
+    }
+
+    #[test]
+    fn test_jump_into_middle_of_instruction_is_ignored() {
+        // JMP 1 lands inside its own operand bytes
+        let mut code = vec![op::JMP];
+        code.extend(&1u32.to_be_bytes());
+        code.push(op::HALT);
+
+        let cfg = ControlFlowGraph::build(&code);
+        assert_eq!(cfg.blocks.len(), 2);
+        assert!(cfg.edges.is_empty());
+    }
+
+    #[test]
+    fn test_truncated_instruction_ends_the_graph() {
+        let code = vec![op::BIPUSH, 1, op::JMP, 0, 0];
+
+        let cfg = ControlFlowGraph::build(&code);
+        assert_eq!(cfg.blocks, vec![BasicBlock { start: 0, end: 5, instructions: vec![0, 2] }]);
+        assert!(cfg.edges.is_empty());
+    }
+
+    #[test]
+    fn test_dot_output_uses_labels() {
+        let (code, labels) = assemble("
+            start:
+            BIPUSH 1
+            BIPUSH 2
+            CMP
+            JL start
+            HALT
+        ");
+        let dot = ControlFlowGraph::build(&code).to_dot(&code, Some(&labels));
+
+        assert!(dot.starts_with("digraph cfg {"));
+        assert!(dot.contains("block_0000 [label=\"start:\\l0000: BIPUSH 1\\l"));
+        assert!(dot.contains("JL start\\l"));
+        assert!(dot.contains("block_0000 -> block_0000 [label=\"taken\"];"));
+        assert!(dot.contains("block_0000 -> block_000A [label=\"fallthrough\", style=dashed];"));
+        assert!(dot.trim_end().ends_with('}'));
+    }
+
+    #[test]
+    fn test_dot_output_without_labels_uses_addresses() {
+        let (code, _) = assemble("JMP 5\nHALT");
+        let dot = ControlFlowGraph::build(&code).to_dot(&code, None);
+
+        assert!(dot.contains("0000: JMP 5\\l"));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::vm::debug_info::DebugInfo;
+use crate::vm::decoder::decode_at;
+use crate::vm::opcodes::is_conditional_jump;
+use crate::vm::profiler::Profiler;
+
+/// Builds an lcov tracefile for the source described by `debug` from the
+/// execution counts gathered by a `Profiler`. Every instruction line gets a
+/// `DA` record and every conditional jump a taken and a not-taken `BRDA` record.
+pub fn lcov_report(profile: &Profiler, code: &[u8], debug: &DebugInfo) -> String {
+    let lines = &debug.entries;
+    let mut out = String::from("TN:\n");
+    out.push_str(&format!("SF:{}\n", debug.file));
+
+    let mut lines_hit = 0;
+    let mut branches = Vec::new();
+
+    for entry in lines {
+        let addr = entry.addr as usize;
+        let count = profile.address_counts.get(&addr).copied().unwrap_or(0);
+        out.push_str(&format!("DA:{},{}\n", entry.line, count));
+        if count > 0 {
+            lines_hit += 1;
+        }
+
+        let is_branch = addr < code.len()
+            && decode_at(code, addr).is_ok_and(|ins| is_conditional_jump(ins.opcode));
+        if is_branch {
+            branches.push((entry.line, count, profile.branches.get(&addr).copied().unwrap_or_default()));
+        }
+    }
+
+    let mut branches_hit = 0;
+    for (block, (line, count, stats)) in branches.iter().enumerate() {
+        // lcov uses "-" for branches whose line never ran
+        for (branch, taken) in [(0, stats.taken), (1, stats.not_taken)] {
+            let taken = if *count == 0 { "-".to_string() } else { taken.to_string() };
+            if taken != "-" && taken != "0" {
+                branches_hit += 1;
+            }
+            out.push_str(&format!("BRDA:{},{},{},{}\n", line, block, branch, taken));
+        }
+    }
+
+    out.push_str(&format!("BRF:{}\n", branches.len() * 2));
+    out.push_str(&format!("BRH:{}\n", branches_hit));
+    out.push_str(&format!("LF:{}\n", lines.len()));
+    out.push_str(&format!("LH:{}\n", lines_hit));
+    out.push_str("end_of_record\n");
+    out
+}
+
+
+#[cfg(test)]
+mod test_coverage {
+    use super::*;
+    use crate::vm::assembler::Assembler;
+    use crate::vm::runner::VirtualMachine;
+
+    fn coverage(source: &str) -> String {
+        let mut assembler = Assembler::new();
+        let code = assembler.assemble(source).expect("Assembly failed");
+        let mut vm = VirtualMachine::new(code.clone());
+        vm.profiler = Some(Profiler::new());
+        vm.run().unwrap();
+        lcov_report(vm.profiler.as_ref().unwrap(), &code, &assembler.debug_info("prog.flint"))
+    }
+
+    #[test]
+    fn test_lcov_lines_and_branches() {
+        let report = coverage("BIPUSH 1
+BIPUSH 2
+CMP
+JL less
+BIPUSH 99
+less:
+HALT");
+        let lines: Vec<&str> = report.lines().collect();
+
+        assert_eq!(lines, vec![
+            "TN:",
+            "SF:prog.flint",
+            "DA:1,1",
+            "DA:2,1",
+            "DA:3,1",
+            "DA:4,1",
+            "DA:5,0",
+            "DA:7,1",
+            "BRDA:4,0,0,1",
+            "BRDA:4,0,1,0",
+            "BRF:2",
+            "BRH:1",
+            "LF:6",
+            "LH:5",
+            "end_of_record",
+        ]);
+    }
+
+    #[test]
+    fn test_lcov_unexecuted_branch_uses_dash() {
+        let report = coverage("JMP end
+BIPUSH 0
+JE end
+end:
+HALT");
+
+        assert!(report.contains("DA:3,0\n"));
+        assert!(report.contains("BRDA:3,0,0,-\nBRDA:3,0,1,-\n"));
+        assert!(report.contains("BRH:0\n"));
+    }
+}
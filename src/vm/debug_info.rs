@@ -0,0 +1,67 @@
+/// Source position of one emitted instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugEntry {
+    pub addr: u32,
+    /// 1-based line in `DebugInfo::file`.
+    pub line: usize,
+    /// Closest label at or before the instruction, if any.
+    pub label: Option<String>,
+}
+
+/// Debug table produced by the assembler, mapping bytecode addresses back to
+/// the assembly source they came from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugInfo {
+    pub file: String,
+    /// One entry per instruction, sorted by address.
+    pub entries: Vec<DebugEntry>,
+}
+
+impl DebugInfo {
+    /// Finds the entry of the instruction starting at `addr`.
+    pub fn lookup(&self, addr: usize) -> Option<&DebugEntry> {
+        let idx = self.entries.binary_search_by_key(&addr, |e| e.addr as usize).ok()?;
+        Some(&self.entries[idx])
+    }
+
+    /// Describes `addr` as `file:line (in label)` for error messages.
+    pub fn describe(&self, addr: usize) -> Option<String> {
+        let entry = self.lookup(addr)?;
+        let mut text = format!("{}:{}", self.file, entry.line);
+        if let Some(label) = &entry.label {
+            text.push_str(&format!(" (in {})", label));
+        }
+        Some(text)
+    }
+}
+
+
+#[cfg(test)]
+mod test_debug_info {
+    use super::*;
+
+    fn sample() -> DebugInfo {
+        DebugInfo {
+            file: "prog.flint".to_string(),
+            entries: vec![
+                DebugEntry { addr: 0, line: 1, label: None },
+                DebugEntry { addr: 2, line: 3, label: Some("loop".to_string()) },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_lookup_exact_address_only() {
+        let info = sample();
+        assert_eq!(info.lookup(2).unwrap().line, 3);
+        assert!(info.lookup(1).is_none());
+        assert!(info.lookup(9).is_none());
+    }
+
+    #[test]
+    fn test_describe() {
+        let info = sample();
+        assert_eq!(info.describe(0).unwrap(), "prog.flint:1");
+        assert_eq!(info.describe(2).unwrap(), "prog.flint:3 (in loop)");
+    }
+}
@@ -0,0 +1,299 @@
+use crate::vm::opcodes::{is_jump, op, relative_jump_info, TABLESWITCH_HEADER};
+use std::fmt;
+
+/// A decoded instruction operand, typed by how the VM interprets it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    None,
+    /// Signed 32-bit immediate (IPUSH).
+    Int(i32),
+    /// Signed 64-bit immediate (LPUSH).
+    Long(i64),
+    /// 64-bit float immediate (FPUSH).
+    Float(f64),
+    /// Jump target or memory address (JMP, LOAD, STORE, ...).
+    Address(u32),
+    /// Displacement of a PC-relative jump from the jump's own address (JMP8, ...).
+    Offset(i32),
+    /// Single byte immediate (BIPUSH).
+    Byte(u8),
+    /// Header of a TABLESWITCH; the `count` targets follow it in the bytecode.
+    Table { low: i32, default: u32, count: u32 },
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::None => Ok(()),
+            Operand::Int(v) => write!(f, "{}", v),
+            Operand::Long(v) => write!(f, "{}", v),
+            Operand::Float(v) => write!(f, "{:.4}", v),
+            Operand::Address(v) => write!(f, "{}", v),
+            Operand::Offset(v) => write!(f, "{:+}", v),
+            Operand::Byte(v) => write!(f, "{}", *v as i8),
+            Operand::Table { low, default, .. } => write!(f, "{} {}", low, default),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instruction {
+    pub addr: usize,
+    pub opcode: u8,
+    pub operand: Operand,
+}
+
+impl Instruction {
+    pub fn name(&self) -> &'static str {
+        op::get_info(self.opcode).unwrap().name
+    }
+
+    /// Encoded length in bytes, opcode included.
+    pub fn size(&self) -> usize {
+        match self.operand {
+            Operand::Table { count, .. } => TABLESWITCH_HEADER + 4 * count as usize,
+            _ => op::get_info(self.opcode).unwrap().size as usize,
+        }
+    }
+
+    /// Address of the instruction that follows in memory.
+    pub fn next_addr(&self) -> usize {
+        self.addr + self.size()
+    }
+
+    /// The address a jump transfers control to, `None` for non-jumps.
+    pub fn jump_target(&self) -> Option<usize> {
+        match self.operand {
+            Operand::Address(addr) if is_jump(self.opcode) => Some(addr as usize),
+            Operand::Offset(offset) => self.addr.checked_add_signed(offset as isize),
+            _ => None,
+        }
+    }
+
+    /// The jump table of a TABLESWITCH, empty for every other instruction.
+    /// The instruction must have been decoded from `bytecode`.
+    pub fn cases(&self, bytecode: &[u8]) -> Vec<usize> {
+        match self.operand {
+            Operand::Table { .. } => bytecode[self.addr + TABLESWITCH_HEADER..self.next_addr()]
+                .chunks_exact(4)
+                .map(|c| u32::from_be_bytes(c.try_into().unwrap()) as usize)
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Every address the instruction can jump to: the target of a jump, or
+    /// the default followed by the cases of a TABLESWITCH.
+    pub fn targets(&self, bytecode: &[u8]) -> Vec<usize> {
+        match self.operand {
+            Operand::Table { default, .. } => {
+                let mut targets = vec![default as usize];
+                targets.extend(self.cases(bytecode));
+                targets
+            }
+            _ => self.jump_target().into_iter().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The byte at `addr` is not an opcode. Decoding resumes at the next byte.
+    UnknownOpcode { addr: usize, byte: u8 },
+    /// The operand of the instruction at `addr` runs past the end of the code.
+    Truncated { addr: usize, opcode: u8, expected: usize, found: usize },
+}
+
+impl DecodeError {
+    pub fn addr(&self) -> usize {
+        match *self {
+            DecodeError::UnknownOpcode { addr, .. } => addr,
+            DecodeError::Truncated { addr, .. } => addr,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            DecodeError::UnknownOpcode { addr, byte } => {
+                write!(f, "Unknown opcode 0x{:02X} at 0x{:04X}", byte, addr)
+            }
+            DecodeError::Truncated { addr, opcode, expected, found } => write!(
+                f,
+                "Truncated {} at 0x{:04X}: expected {} operand bytes, found {}",
+                op::get_info(opcode).unwrap().name, addr, expected, found
+            ),
+        }
+    }
+}
+
+/// Iterator over the instructions of a bytecode slice. Unknown bytes and a
+/// truncated trailing instruction are reported as errors instead of panicking.
+pub struct Decoder<'a> {
+    bytecode: &'a [u8],
+    ip: usize,
+}
+
+pub fn decode(bytecode: &[u8]) -> Decoder<'_> {
+    Decoder { bytecode, ip: 0 }
+}
+
+/// Decodes the single instruction starting at `addr`.
+pub fn decode_at(bytecode: &[u8], addr: usize) -> Result<Instruction, DecodeError> {
+    let cur = bytecode[addr];
+    let info = op::get_info(cur).ok_or(DecodeError::UnknownOpcode { addr, byte: cur })?;
+    let size = info.size as usize;
+
+    let bytes = bytecode.get(addr + 1..addr + size).ok_or(DecodeError::Truncated {
+        addr,
+        opcode: cur,
+        expected: size - 1,
+        found: bytecode.len() - addr - 1,
+    })?;
+
+    let operand = match size {
+        _ if relative_jump_info(cur).is_some() => Operand::Offset(match size {
+            2 => bytes[0] as i8 as i32,
+            3 => i16::from_be_bytes(bytes.try_into().unwrap()) as i32,
+            _ => i32::from_be_bytes(bytes.try_into().unwrap()),
+        }),
+        2 => Operand::Byte(bytes[0]),
+        5 => {
+            let val = u32::from_be_bytes(bytes.try_into().unwrap());
+            if cur == op::IPUSH { Operand::Int(val as i32) } else { Operand::Address(val) }
+        }
+        9 if cur == op::LPUSH => Operand::Long(i64::from_be_bytes(bytes.try_into().unwrap())),
+        9 => Operand::Float(f64::from_be_bytes(bytes.try_into().unwrap())),
+        TABLESWITCH_HEADER => {
+            let word = |i: usize| u32::from_be_bytes(bytes[i..i + 4].try_into().unwrap());
+            let count = word(8);
+            let table = 4 * count as usize;
+            if bytecode.len() - addr - size < table {
+                return Err(DecodeError::Truncated {
+                    addr,
+                    opcode: cur,
+                    expected: size - 1 + table,
+                    found: bytecode.len() - addr - 1,
+                });
+            }
+            Operand::Table { low: word(0) as i32, default: word(4), count }
+        }
+        _ => Operand::None,
+    };
+
+    Ok(Instruction { addr, opcode: cur, operand })
+}
+
+impl Iterator for Decoder<'_> {
+    type Item = Result<Instruction, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ip >= self.bytecode.len() {
+            return None;
+        }
+
+        let result = decode_at(self.bytecode, self.ip);
+        self.ip = match &result {
+            Ok(ins) => ins.next_addr(),
+            Err(DecodeError::UnknownOpcode { addr, .. }) => addr + 1,
+            // Nothing after a truncated instruction can be decoded
+            Err(DecodeError::Truncated { .. }) => self.bytecode.len(),
+        };
+        Some(result)
+    }
+}
+
+
+#[cfg(test)]
+mod test_decoder {
+    use super::*;
+
+    #[test]
+    fn test_decode_typed_operands() {
+        let mut bytecode = vec![op::BIPUSH, 200, op::IPUSH];
+        bytecode.extend(&(-7i32).to_be_bytes());
+        bytecode.push(op::FPUSH);
+        bytecode.extend(&2.5f64.to_be_bytes());
+        bytecode.push(op::JMP);
+        bytecode.extend(&0u32.to_be_bytes());
+        bytecode.push(op::ADD);
+        bytecode.push(op::LPUSH);
+        bytecode.extend(&(-1i64 << 40).to_be_bytes());
+
+        let decoded: Vec<Instruction> = decode(&bytecode).map(|r| r.unwrap()).collect();
+
+        assert_eq!(decoded, vec![
+            Instruction { addr: 0, opcode: op::BIPUSH, operand: Operand::Byte(200) },
+            Instruction { addr: 2, opcode: op::IPUSH, operand: Operand::Int(-7) },
+            Instruction { addr: 7, opcode: op::FPUSH, operand: Operand::Float(2.5) },
+            Instruction { addr: 16, opcode: op::JMP, operand: Operand::Address(0) },
+            Instruction { addr: 21, opcode: op::ADD, operand: Operand::None },
+            Instruction { addr: 22, opcode: op::LPUSH, operand: Operand::Long(-1 << 40) },
+        ]);
+        assert_eq!(decoded[3].jump_target(), Some(0));
+        assert_eq!(decoded[1].jump_target(), None);
+    }
+
+    #[test]
+    fn test_decode_relative_jumps() {
+        let mut bytecode = vec![op::NOP, op::JMP8, 0xFF, op::JL16];
+        bytecode.extend(&300i16.to_be_bytes());
+        bytecode.push(op::JT32);
+        bytecode.extend(&(-6i32).to_be_bytes());
+
+        let decoded: Vec<Instruction> = decode(&bytecode).map(|r| r.unwrap()).collect();
+        assert_eq!(decoded[1], Instruction { addr: 1, opcode: op::JMP8, operand: Operand::Offset(-1) });
+        assert_eq!(decoded[2], Instruction { addr: 3, opcode: op::JL16, operand: Operand::Offset(300) });
+        assert_eq!(decoded[3], Instruction { addr: 6, opcode: op::JT32, operand: Operand::Offset(-6) });
+        assert_eq!(decoded.iter().map(|i| i.jump_target()).collect::<Vec<_>>(), vec![None, Some(0), Some(303), Some(0)]);
+        assert_eq!(decoded[2].operand.to_string(), "+300");
+
+        // A displacement before the start of the code has no target
+        assert_eq!(decode_at(&[op::JMP8, 0xFE], 0).unwrap().jump_target(), None);
+    }
+
+    #[test]
+    fn test_decode_tableswitch() {
+        let mut bytecode = vec![op::NOP, op::TABLESWITCH];
+        bytecode.extend(&(-1i32).to_be_bytes());
+        bytecode.extend(&30u32.to_be_bytes());
+        bytecode.extend(&2u32.to_be_bytes());
+        bytecode.extend(&10u32.to_be_bytes());
+        bytecode.extend(&20u32.to_be_bytes());
+        bytecode.push(op::HALT);
+
+        let decoded: Vec<Instruction> = decode(&bytecode).map(|r| r.unwrap()).collect();
+        assert_eq!(decoded[1].operand, Operand::Table { low: -1, default: 30, count: 2 });
+        assert_eq!(decoded[1].size(), 21);
+        assert_eq!(decoded[1].cases(&bytecode), vec![10, 20]);
+        assert_eq!(decoded[1].targets(&bytecode), vec![30, 10, 20]);
+        assert_eq!(decoded[2].addr, 22);
+
+        // The table must fit in the code
+        bytecode.truncate(19);
+        assert_eq!(
+            decode_at(&bytecode, 1),
+            Err(DecodeError::Truncated { addr: 1, opcode: op::TABLESWITCH, expected: 20, found: 17 })
+        );
+    }
+
+    #[test]
+    fn test_decode_reports_unknown_and_continues() {
+        let decoded: Vec<_> = decode(&[0xFF, op::HALT]).collect();
+
+        assert_eq!(decoded[0], Err(DecodeError::UnknownOpcode { addr: 0, byte: 0xFF }));
+        assert_eq!(decoded[1].unwrap().opcode, op::HALT);
+    }
+
+    #[test]
+    fn test_decode_reports_truncation_and_stops() {
+        let decoded: Vec<_> = decode(&[op::NOP, op::IPUSH, 1]).collect();
+
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(
+            decoded[1],
+            Err(DecodeError::Truncated { addr: 1, opcode: op::IPUSH, expected: 4, found: 1 })
+        );
+    }
+}
@@ -1,77 +1,198 @@
+use crate::vm::decoder::{decode, decode_at, DecodeError, Instruction, Operand};
 use crate::vm::opcodes::op;
+use std::collections::{BTreeSet, HashMap};
+
+/// Disassembles bytecode into one annotated line per instruction. Malformed
+/// input never panics: truncated instructions and jumps that do not land on
+/// an instruction boundary are flagged inline and decoding carries on.
+pub fn disassemble_bytecode(bytecode: &[u8]) -> String {
+    let decoded: Vec<Result<Instruction, DecodeError>> = decode(bytecode).collect();
+
+    // A jump may land on any decoded address, or just past the end of the code
+    let starts: BTreeSet<usize> = decoded
+        .iter()
+        .map(|entry| match entry {
+            Ok(ins) => ins.addr,
+            Err(e) => e.addr(),
+        })
+        .chain([bytecode.len()])
+        .collect();
 
-pub fn disassemble_bytecode(bytecode: Vec<u8>) -> String {
-    let mut ip = 0;
     let mut asm = String::new();
-
-    while ip < bytecode.len() {
-        let cur = bytecode[ip];
-        
-        // Retrieve metadata from our centralized opcode definition
-        let info = match op::get_info(cur) {
-            Some(i) => i,
-            None => {
-                asm.push_str(&format!("{:04X}: {:02X} UNKNOWN\n", ip, cur));
-                ip += 1;
+    for entry in &decoded {
+        let ins = match *entry {
+            Ok(ins) => ins,
+            Err(DecodeError::UnknownOpcode { addr, byte }) => {
+                asm.push_str(&format!("{:04X}: {:02X} UNKNOWN\n", addr, byte));
+                continue;
+            }
+            Err(DecodeError::Truncated { addr, opcode, expected, found }) => {
+                let name = op::get_info(opcode).unwrap().name;
+                asm.push_str(&format!(
+                    "{:04X}: {:02X} {:<10} ; truncated: expected {} operand bytes, found {}\n",
+                    addr, opcode, name, expected, found
+                ));
                 continue;
             }
         };
 
-        let prefix = format!("{:04X}: {:02X}", ip, cur);
-        let name = info.name;
-
-        match info.size {
-            1 => {
-                // No arguments (e.g., ADD, HALT, POP)
-                asm.push_str(&format!("{} {}\n", prefix, name));
-                ip += 1;
+        let prefix = format!("{:04X}: {:02X}", ins.addr, ins.opcode);
+        let name = ins.name();
+
+        match ins.operand {
+            // No arguments (e.g., ADD, HALT, POP)
+            Operand::None => asm.push_str(&format!("{} {}\n", prefix, name)),
+            // 1-byte argument (e.g., BIPUSH)
+            Operand::Byte(val) => asm.push_str(&format!("{} {:<10} {}\n", prefix, name, val as i8)),
+            Operand::Int(val) => asm.push_str(&format!("{} {:<10} {}\n", prefix, name, val)),
+            Operand::Long(val) => asm.push_str(&format!("{} {:<10} {}\n", prefix, name, val)),
+            Operand::Address(val) => {
+                // Use {:<8} to give the decimal value a consistent 8-character width
+                // This ensures the (0xXX) part starts at the same column every time
+                let line = format!("{} {:<10} {:<8} (0x{:02X})", prefix, name, val, val);
+                match ins.jump_target() {
+                    Some(target) if !starts.contains(&target) => {
+                        asm.push_str(&format!("{} ; invalid jump target\n", line));
+                    }
+                    _ => asm.push_str(&format!("{}\n", line)),
+                }
             }
-            2 => {
-                // 1-byte argument (e.g., BIPUSH)
-                let val = bytecode[ip + 1];
-                asm.push_str(&format!("{} {:<10} {}\n", prefix, name, val as i8));
-                ip += 2;
+            // Relative jumps show the displacement and the address it resolves to
+            Operand::Offset(val) => {
+                let line = format!("{} {:<10} {:<8}", prefix, name, format!("{:+}", val));
+                match ins.jump_target() {
+                    Some(target) if starts.contains(&target) => {
+                        asm.push_str(&format!("{} (0x{:02X})\n", line, target));
+                    }
+                    _ => asm.push_str(&format!("{} ; invalid jump target\n", line)),
+                }
             }
-            5 => {
-                // 4-byte argument (e.g., IPUSH, JMP, LOAD, STORE)
-                let bytes = &bytecode[ip + 1..ip + 5];
-                let val = u32::from_be_bytes(bytes.try_into().unwrap());
-                
-                if cur == op::IPUSH {
-                    asm.push_str(&format!("{} {:<10} {}\n", prefix, name, val as i32));
+            // 8-byte argument (e.g., FPUSH)
+            Operand::Float(val) => asm.push_str(&format!("{} {:<10} {:.4}\n", prefix, name, val)),
+            // Selector base and default target, then the jump table
+            Operand::Table { low, default, .. } => {
+                let mut line = format!("{} {:<10} {} {}", prefix, name, low, default);
+                for case in ins.cases(bytecode) {
+                    line.push_str(&format!(" {}", case));
+                }
+                if ins.targets(bytecode).iter().any(|t| !starts.contains(t)) {
+                    line.push_str(" ; invalid jump target");
+                }
+                asm.push_str(&format!("{}\n", line));
+            }
+        }
+    }
+    asm
+}
+
+/// Disassembles bytecode into source that `Assembler::assemble` accepts and
+/// encodes back to the same bytes. Every valid jump target gets a synthesized
+/// `L_XXXX:` label and jump operands refer to those labels.
+pub fn disassemble_to_source(bytecode: &[u8]) -> Result<String, String> {
+    let instructions: Vec<Instruction> = decode(bytecode)
+        .collect::<Result<_, _>>()
+        .map_err(|e: DecodeError| e.to_string())?;
+
+    // A label may also sit just past the last instruction
+    let starts: BTreeSet<usize> = instructions.iter().map(|i| i.addr).chain([bytecode.len()]).collect();
+    let labels: BTreeSet<usize> = instructions
+        .iter()
+        .flat_map(|i| i.targets(bytecode))
+        .filter(|t| starts.contains(t))
+        .collect();
+
+    let mut asm = String::new();
+    for ins in &instructions {
+        if labels.contains(&ins.addr) {
+            asm.push_str(&format!("L_{:04X}:\n", ins.addr));
+        }
+
+        let name = ins.name();
+        match ins.operand {
+            Operand::None => asm.push_str(&format!("    {}\n", name)),
+            Operand::Byte(val) => asm.push_str(&format!("    {} {}\n", name, val)),
+            Operand::Int(val) => asm.push_str(&format!("    {} {}\n", name, val)),
+            Operand::Long(val) => asm.push_str(&format!("    {} {}\n", name, val)),
+            Operand::Address(val) => {
+                if labels.contains(&(val as usize)) && ins.jump_target().is_some() {
+                    asm.push_str(&format!("    {} L_{:04X}\n", name, val));
                 } else {
-                    // Use {:<8} to give the decimal value a consistent 8-character width
-                    // This ensures the (0xXX) part starts at the same column every time
-                    asm.push_str(&format!("{} {:<10} {:<8} (0x{:02X})\n", prefix, name, val, val));
+                    asm.push_str(&format!("    {} {}\n", name, val));
                 }
-                ip += 5;
             }
-            9 => {
-                // 8-byte argument (e.g., FPUSH)
-                let bytes = &bytecode[ip + 1..ip + 9];
-                let val = f64::from_be_bytes(bytes.try_into().unwrap());
-                asm.push_str(&format!("{} {:<10} {:.4}\n", prefix, name, val));
-                ip += 9;
+            Operand::Offset(val) => match ins.jump_target().filter(|t| labels.contains(t)) {
+                Some(target) => asm.push_str(&format!("    {} L_{:04X}\n", name, target)),
+                // A numeric operand is read back as the displacement itself
+                None => asm.push_str(&format!("    {} {}\n", name, val)),
+            },
+            // Debug formatting is the shortest text that parses back to the same f64
+            Operand::Float(val) => asm.push_str(&format!("    {} {:?}\n", name, val)),
+            Operand::Table { low, .. } => {
+                let targets: Vec<String> = ins.targets(bytecode).into_iter().map(|t| match labels.contains(&t) {
+                    true => format!("L_{:04X}", t),
+                    false => t.to_string(),
+                }).collect();
+                asm.push_str(&format!("    {} {} {}\n", name, low, targets.join(" ")));
             }
-            _ => {
-                asm.push_str(&format!("{} {}\n", prefix, name));
-                ip += 1;
+        }
+    }
+
+    if labels.contains(&bytecode.len()) {
+        asm.push_str(&format!("L_{:04X}:\n", bytecode.len()));
+    }
+    Ok(asm)
+}
+
+
+/// Inverts the assembler's symbol table. When several labels share an address
+/// the alphabetically first one is used so the output is deterministic.
+pub fn label_names(labels: Option<&HashMap<String, u32>>) -> HashMap<usize, String> {
+    let mut names: HashMap<usize, String> = HashMap::new();
+    for (name, &addr) in labels.into_iter().flatten() {
+        let entry = names.entry(addr as usize).or_insert_with(|| name.clone());
+        if name < entry {
+            *entry = name.clone();
+        }
+    }
+    names
+}
+
+/// Formats one instruction as `MNEMONIC operand`, naming jump targets
+/// from `names` when a label exists for them.
+pub fn format_instruction(bytecode: &[u8], addr: usize, names: &HashMap<usize, String>) -> String {
+    match decode_at(bytecode, addr) {
+        Ok(ins) => match ins.operand {
+            Operand::None => ins.name().to_string(),
+            Operand::Table { low, .. } => {
+                let targets: Vec<String> = ins.targets(bytecode).into_iter().map(|t| match names.get(&t) {
+                    Some(name) => name.clone(),
+                    None => t.to_string(),
+                }).collect();
+                format!("{} {} {}", ins.name(), low, targets.join(" "))
             }
+            _ => match ins.jump_target().and_then(|t| names.get(&t)) {
+                Some(name) => format!("{} {}", ins.name(), name),
+                None => format!("{} {}", ins.name(), ins.operand),
+            },
+        },
+        Err(DecodeError::UnknownOpcode { byte, .. }) => format!("UNKNOWN 0x{:02X}", byte),
+        Err(DecodeError::Truncated { opcode, .. }) => {
+            format!("{} <truncated>", op::get_info(opcode).unwrap().name)
         }
     }
-    asm
 }
 
 
 #[cfg(test)]
 mod test_disassembler {
     use super::*;
+    use crate::vm::assembler::Assembler;
     use crate::vm::opcodes::op;
 
     #[test]
     fn test_disassemble_single_byte_instructions() {
         let bytecode = vec![op::ADD, op::HALT];
-        let result = disassemble_bytecode(bytecode);
+        let result = disassemble_bytecode(&bytecode);
 
         let lines: Vec<&str> = result.lines().collect();
         assert_eq!(lines.len(), 2);
@@ -88,7 +209,7 @@ mod test_disassembler {
     fn test_disassemble_two_byte_instruction() {
         // Testing 2-byte instruction: BIPUSH 10
         let bytecode = vec![op::BIPUSH, 10];
-        let result = disassemble_bytecode(bytecode);
+        let result = disassemble_bytecode(&bytecode);
 
         assert!(result.contains("0000: 03 BIPUSH     10"));
     }
@@ -100,7 +221,7 @@ mod test_disassembler {
         let mut bytecode = vec![op::IPUSH];
         bytecode.extend(&val.to_be_bytes());
         
-        let result = disassemble_bytecode(bytecode);
+        let result = disassemble_bytecode(&bytecode);
         assert!(result.contains("0000: 02 IPUSH      -500"));
     }
 
@@ -111,7 +232,7 @@ mod test_disassembler {
         let mut bytecode = vec![op::LOAD];
         bytecode.extend(&10u32.to_be_bytes());
         
-        let result = disassemble_bytecode(bytecode);
+        let result = disassemble_bytecode(&bytecode);
         
         // Check for specific spacing: LOAD (4 chars) + 6 spaces = 10 total width
         // Then 10 (2 chars) + 6 spaces = 8 total width
@@ -125,15 +246,27 @@ mod test_disassembler {
         let mut bytecode = vec![op::FPUSH];
         bytecode.extend(&val.to_be_bytes());
 
-        let result = disassemble_bytecode(bytecode);
+        let result = disassemble_bytecode(&bytecode);
         assert!(result.contains("0000: 04 FPUSH      42.5000"));
     }
 
+    #[test]
+    fn test_disassemble_math_instructions() {
+        let bytecode = vec![op::SQRT, op::POW, op::LOG, op::COS, op::ABS, op::FLOOR, op::MAX];
+        let names: Vec<String> = disassemble_bytecode(&bytecode)
+            .lines()
+            .map(|l| l.split_whitespace().nth(2).unwrap().to_string())
+            .collect();
+
+        assert_eq!(names, vec!["SQRT", "POW", "LOG", "COS", "ABS", "FLOOR", "MAX"]);
+        assert_eq!(disassemble_to_source(&bytecode).unwrap(), "    SQRT\n    POW\n    LOG\n    COS\n    ABS\n    FLOOR\n    MAX\n");
+    }
+
     #[test]
     fn test_disassemble_unknown_opcode() {
         // Testing an opcode that doesn't exist (e.g., 0xFF)
         let bytecode = vec![0xFF];
-        let result = disassemble_bytecode(bytecode);
+        let result = disassemble_bytecode(&bytecode);
 
         assert!(result.contains("0000: FF UNKNOWN"));
     }
@@ -147,7 +280,7 @@ mod test_disassembler {
         bytecode.extend(&20u32.to_be_bytes());
         bytecode.push(op::HALT);                // 1 byte
 
-        let result = disassemble_bytecode(bytecode);
+        let result = disassemble_bytecode(&bytecode);
         let lines: Vec<&str> = result.lines().collect();
 
         assert_eq!(lines.len(), 4);
@@ -164,7 +297,7 @@ mod test_disassembler {
         bytecode.push(op::STORE);
         bytecode.extend(&500u32.to_be_bytes());
 
-        let result = disassemble_bytecode(bytecode);
+        let result = disassemble_bytecode(&bytecode);
         let lines: Vec<&str> = result.lines().collect();
 
         // Find the index of the '(' character in both lines
@@ -179,7 +312,7 @@ mod test_disassembler {
         let mut bytecode = vec![op::JMP];
         bytecode.extend(&1000u32.to_be_bytes()); // 0x03E8
         
-        let result = disassemble_bytecode(bytecode);
+        let result = disassemble_bytecode(&bytecode);
         // Should show (0x3E8) or (0x03E8) depending on your width
         assert!(result.contains("(0x3E8)") || result.contains("(0x03E8)"));
     }
@@ -191,7 +324,7 @@ mod test_disassembler {
         bytecode.push(op::JMP);
         bytecode.extend(&10u32.to_be_bytes());
 
-        let result = disassemble_bytecode(bytecode);
+        let result = disassemble_bytecode(&bytecode);
         let lines: Vec<&str> = result.lines().collect();
 
         assert!(lines[0].contains("-10"));
@@ -204,14 +337,195 @@ mod test_disassembler {
         let mut bytecode = vec![op::FPUSH];
         bytecode.extend(&1.2345678f64.to_be_bytes());
 
-        let result = disassemble_bytecode(bytecode);
+        let result = disassemble_bytecode(&bytecode);
         // Should round to 1.2346 or 1.2345 based on Rust's default formatting
         assert!(result.contains("1.2346")); 
     }
 
+    #[test]
+    fn test_disassemble_bitwise_instructions() {
+        let result = disassemble_bytecode(&[op::AND, op::USHR]);
+        let lines: Vec<&str> = result.lines().collect();
+
+        assert_eq!(lines[0], format!("0000: {:02X} AND", op::AND));
+        assert_eq!(lines[1], format!("0001: {:02X} USHR", op::USHR));
+    }
+
     #[test]
     fn test_disassemble_empty_bytecode() {
-        let result = disassemble_bytecode(vec![]);
+        let result = disassemble_bytecode(&[]);
         assert_eq!(result, "");
     }
+
+    #[test]
+    fn test_disassemble_truncated_instruction_is_flagged() {
+        let bytecode = vec![op::BIPUSH, 5, op::IPUSH, 0, 0];
+        let result = disassemble_bytecode(&bytecode);
+        let lines: Vec<&str> = result.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("BIPUSH     5"));
+        assert!(lines[1].starts_with("0002:"));
+        assert!(lines[1].contains("IPUSH"));
+        assert!(lines[1].contains("truncated: expected 4 operand bytes, found 2"));
+    }
+
+    #[test]
+    fn test_disassemble_truncated_opcode_without_operand() {
+        let result = disassemble_bytecode(&[op::FPUSH]);
+        assert!(result.contains("truncated: expected 8 operand bytes, found 0"));
+    }
+
+    #[test]
+    fn test_disassemble_flags_invalid_jump_targets() {
+        // Target 2 is inside the JMP itself, 100 is past the end, 5 is the HALT
+        let mut bytecode = vec![op::JMP];
+        bytecode.extend(&2u32.to_be_bytes());
+        bytecode.push(op::HALT);
+        bytecode.push(op::JE);
+        bytecode.extend(&100u32.to_be_bytes());
+        bytecode.push(op::JNE);
+        bytecode.extend(&5u32.to_be_bytes());
+
+        let result = disassemble_bytecode(&bytecode);
+        let lines: Vec<&str> = result.lines().collect();
+
+        assert!(lines[0].ends_with("; invalid jump target"));
+        assert!(lines[2].ends_with("; invalid jump target"));
+        assert!(!lines[3].contains("invalid"));
+    }
+
+    #[test]
+    fn test_disassemble_continues_after_unknown_byte() {
+        let result = disassemble_bytecode(&[0xFF, op::HALT]);
+        let lines: Vec<&str> = result.lines().collect();
+
+        assert_eq!(lines, vec!["0000: FF UNKNOWN", &format!("0001: {:02X} HALT", op::HALT)]);
+    }
+
+    fn assert_round_trip(bytecode: &[u8]) {
+        let source = disassemble_to_source(bytecode).expect("Disassembly failed");
+        let reassembled = Assembler::new().assemble(&source).expect("Reassembly failed");
+        assert_eq!(reassembled, bytecode, "Round trip changed the bytecode:\n{}", source);
+    }
+
+    #[test]
+    fn test_source_synthesizes_labels_for_jump_targets() {
+        let bytecode = Assembler::new().assemble("
+            BIPUSH 10
+            loop:
+            BIPUSH 1
+            SUB
+            DUP
+            BIPUSH 0
+            CMP
+            JG loop
+            HALT
+        ").unwrap();
+
+        let source = disassemble_to_source(&bytecode).unwrap();
+        let lines: Vec<&str> = source.lines().collect();
+
+        assert_eq!(lines[0], "    BIPUSH 10");
+        assert_eq!(lines[1], "L_0002:");
+        assert!(lines.contains(&"    JG L_0002"));
+        assert!(!source.contains("0x"), "Addresses and hex bytes should be omitted");
+        assert_round_trip(&bytecode);
+    }
+
+    #[test]
+    fn test_source_round_trips_all_operand_kinds() {
+        let mut bytecode = vec![op::BIPUSH, 200];
+        bytecode.push(op::IPUSH);
+        bytecode.extend(&(-500i32).to_be_bytes());
+        bytecode.push(op::FPUSH);
+        bytecode.extend(&0.1f64.to_be_bytes());
+        bytecode.push(op::FPUSH);
+        bytecode.extend(&f64::NAN.to_be_bytes());
+        bytecode.push(op::LPUSH);
+        bytecode.extend(&i64::MIN.to_be_bytes());
+        bytecode.push(op::STORE);
+        bytecode.extend(&u32::MAX.to_be_bytes());
+        bytecode.push(op::NEW);
+        bytecode.extend(&(i32::MAX as u32 + 1).to_be_bytes());
+        bytecode.push(op::HALT);
+
+        let source = disassemble_to_source(&bytecode).unwrap();
+        assert!(source.contains("    STORE 4294967295\n"), "Addresses are printed unsigned");
+        assert!(source.contains("    NEW 2147483648\n"));
+        assert_round_trip(&bytecode);
+    }
+
+    #[test]
+    fn test_source_labels_target_past_end() {
+        let mut bytecode = vec![op::JMP];
+        bytecode.extend(&5u32.to_be_bytes());
+
+        let source = disassemble_to_source(&bytecode).unwrap();
+        assert_eq!(source, "    JMP L_0005\nL_0005:\n");
+        assert_round_trip(&bytecode);
+    }
+
+    #[test]
+    fn test_source_keeps_numeric_operand_for_mid_instruction_target() {
+        let mut bytecode = vec![op::JMP];
+        bytecode.extend(&2u32.to_be_bytes());
+        bytecode.push(op::HALT);
+
+        let source = disassemble_to_source(&bytecode).unwrap();
+        assert!(source.contains("    JMP 2"));
+        assert_round_trip(&bytecode);
+    }
+
+    #[test]
+    fn test_relative_jumps_show_resolved_target() {
+        let bytecode = vec![op::NOP, op::JMP8, 0xFF, op::JG16, 0x7F, 0xFF];
+        let result = disassemble_bytecode(&bytecode);
+        let lines: Vec<&str> = result.lines().collect();
+
+        assert_eq!(lines[1], format!("0001: {:02X} JMP8       -1       (0x00)", op::JMP8));
+        assert!(lines[2].ends_with("+32767   ; invalid jump target"));
+
+        let source = disassemble_to_source(&bytecode).unwrap();
+        assert_eq!(source, "L_0000:\n    NOP\n    JMP8 L_0000\n    JG16 32767\n");
+        assert_round_trip(&bytecode);
+    }
+
+    #[test]
+    fn test_relaxed_program_round_trips() {
+        let source = format!("loop:\nDUP\nJT done\n{}JMP loop\ndone:\nHALT", "NOP\n".repeat(150));
+        let bytecode = Assembler::new().with_relative_jumps().assemble(&source).unwrap();
+        assert_round_trip(&bytecode);
+    }
+
+    #[test]
+    fn test_tableswitch_lists_targets() {
+        let bytecode = Assembler::new().assemble("
+            BIPUSH 1
+            TABLESWITCH 0 other zero one
+            zero:
+            HALT
+            one:
+            NOP
+            other:
+            HALT
+        ").unwrap();
+
+        let result = disassemble_bytecode(&bytecode);
+        let lines: Vec<&str> = result.lines().collect();
+        assert_eq!(lines[1], format!("0002: {:02X} TABLESWITCH 0 25 23 24", op::TABLESWITCH));
+
+        let source = disassemble_to_source(&bytecode).unwrap();
+        assert!(source.contains("    TABLESWITCH 0 L_0019 L_0017 L_0018\n"));
+        assert_round_trip(&bytecode);
+
+        let names = HashMap::from([(23, "zero".to_string())]);
+        assert_eq!(format_instruction(&bytecode, 2, &names), "TABLESWITCH 0 25 zero 24");
+    }
+
+    #[test]
+    fn test_source_rejects_unknown_and_truncated_bytecode() {
+        assert!(disassemble_to_source(&[0xFF]).is_err());
+        assert!(disassemble_to_source(&[op::IPUSH, 0, 0]).is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,262 @@
+use crate::vm::runner::Value;
+
+/// Heap size, in slots, that triggers the first automatic collection.
+pub const INITIAL_GC_THRESHOLD: usize = 1024;
+
+/// An object on the VM heap, referenced from the stack or memory by `Value::Ref`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+    Array(Vec<Value>),
+    /// Instance of the struct at index `layout` of `VirtualMachine::structs`
+    Record { layout: usize, fields: Vec<Value> },
+}
+
+impl Object {
+    /// Heap usage of the object: one slot for the object plus one per value it holds
+    pub fn slots(&self) -> usize {
+        1 + self.values().len()
+    }
+
+    /// The values stored in the object, which may reference other objects
+    pub fn values(&self) -> &[Value] {
+        match self {
+            Object::Array(values) => values,
+            Object::Record { fields, .. } => fields,
+        }
+    }
+
+    pub fn values_mut(&mut self) -> &mut [Value] {
+        match self {
+            Object::Array(values) => values,
+            Object::Record { fields, .. } => fields,
+        }
+    }
+}
+
+/// Field layout of a record type, declared with `.struct Name field...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StructLayout {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+/// Counters kept across garbage collections.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub collections: u64,
+    pub objects_freed: u64,
+    pub slots_freed: u64,
+    /// Largest heap size, in slots, seen so far
+    pub peak_slots: usize,
+}
+
+/// Storage for heap objects. A reference is the index of its object's slot;
+/// slots freed by the collector are reused by later allocations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heap {
+    objects: Vec<Option<Object>>,
+    free: Vec<usize>,
+    /// Live heap size in slots, see `Object::slots`
+    size: usize,
+    /// Size at which the next automatic collection runs
+    next_gc: usize,
+    pub stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self { objects: Vec::new(), free: Vec::new(), size: 0, next_gc: INITIAL_GC_THRESHOLD, stats: GcStats::default() }
+    }
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of live objects
+    pub fn len(&self) -> usize {
+        self.objects.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Live heap size in slots
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Size at which the next automatic collection runs
+    pub fn gc_threshold(&self) -> usize {
+        self.next_gc
+    }
+
+    /// Sets the size at which the next automatic collection runs
+    pub fn with_gc_threshold(mut self, threshold: usize) -> Self {
+        self.next_gc = threshold;
+        self
+    }
+
+    /// Whether allocating `slots` more should first run an automatic collection
+    pub fn wants_collection(&self, slots: usize) -> bool {
+        self.size + slots > self.next_gc
+    }
+
+    /// Stores `object` and returns the reference to it
+    pub fn alloc(&mut self, object: Object) -> usize {
+        self.size += object.slots();
+        self.stats.peak_slots = self.stats.peak_slots.max(self.size);
+        match self.free.pop() {
+            Some(reference) => {
+                self.objects[reference] = Some(object);
+                reference
+            }
+            None => {
+                self.objects.push(Some(object));
+                self.objects.len() - 1
+            }
+        }
+    }
+
+    /// Releases an object, used to undo its allocation when stepping back
+    pub fn free(&mut self, reference: usize) -> Option<Object> {
+        let object = self.objects.get_mut(reference)?.take()?;
+        self.size -= object.slots();
+        self.free.push(reference);
+        Some(object)
+    }
+
+    pub fn get(&self, reference: usize) -> Option<&Object> {
+        self.objects.get(reference)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, reference: usize) -> Option<&mut Object> {
+        self.objects.get_mut(reference)?.as_mut()
+    }
+
+    /// Every live object with its reference, in slot order
+    pub fn objects(&self) -> impl Iterator<Item = (usize, &Object)> {
+        self.objects.iter().enumerate().filter_map(|(r, o)| Some((r, o.as_ref()?)))
+    }
+
+    /// Every slot in order, None for the free ones
+    pub fn slots(&self) -> impl Iterator<Item = Option<&Object>> {
+        self.objects.iter().map(Option::as_ref)
+    }
+
+    /// Mark and sweep: frees every object not reachable from `roots` and
+    /// returns how many were freed.
+    pub fn collect<'a>(&mut self, roots: impl Iterator<Item = &'a Value>) -> usize {
+        let mut marked = vec![false; self.objects.len()];
+        let mut pending: Vec<usize> = roots.filter_map(|v| match *v {
+            Value::Ref(r) => Some(r),
+            _ => None,
+        }).collect();
+
+        while let Some(reference) = pending.pop() {
+            if marked.get(reference).copied() != Some(false) {
+                continue;
+            }
+            marked[reference] = true;
+            if let Some(object) = &self.objects[reference] {
+                pending.extend(object.values().iter().filter_map(|v| match *v {
+                    Value::Ref(r) => Some(r),
+                    _ => None,
+                }));
+            }
+        }
+
+        let mut freed = 0;
+        for (reference, live) in marked.into_iter().enumerate() {
+            if !live && let Some(object) = self.free(reference) {
+                freed += 1;
+                self.stats.slots_freed += object.slots() as u64;
+            }
+        }
+        self.stats.collections += 1;
+        self.stats.objects_freed += freed as u64;
+        self.next_gc = (self.size * 2).max(INITIAL_GC_THRESHOLD);
+        freed
+    }
+}
+
+impl From<Vec<Option<Object>>> for Heap {
+    fn from(objects: Vec<Option<Object>>) -> Self {
+        let free = (0..objects.len()).rev().filter(|&r| objects[r].is_none()).collect();
+        let size = objects.iter().flatten().map(Object::slots).sum();
+        Self { objects, free, size, next_gc: INITIAL_GC_THRESHOLD, stats: GcStats { peak_slots: size, ..GcStats::default() } }
+    }
+}
+
+
+#[cfg(test)]
+mod test_heap {
+    use super::*;
+
+    fn array(values: Vec<Value>) -> Object {
+        Object::Array(values)
+    }
+
+    #[test]
+    fn test_collect_frees_unreachable_objects() {
+        let mut heap = Heap::new();
+        let leaf = heap.alloc(array(vec![Value::Int(1)]));
+        let root = heap.alloc(array(vec![Value::Ref(leaf)]));
+        let garbage = heap.alloc(array(vec![Value::Int(2), Value::Int(3)]));
+
+        assert_eq!(heap.collect([Value::Ref(root)].iter()), 1);
+        assert!(heap.get(garbage).is_none());
+        assert!(heap.get(leaf).is_some());
+        assert_eq!(heap.size(), 4);
+        assert_eq!(heap.stats, GcStats { collections: 1, objects_freed: 1, slots_freed: 3, peak_slots: 7 });
+    }
+
+    #[test]
+    fn test_collect_handles_cycles() {
+        let mut heap = Heap::new();
+        let a = heap.alloc(array(vec![Value::Int(0)]));
+        let b = heap.alloc(array(vec![Value::Ref(a)]));
+        if let Some(Object::Array(values)) = heap.get_mut(a) {
+            values[0] = Value::Ref(b);
+        }
+
+        assert_eq!(heap.collect([Value::Ref(b)].iter()), 0);
+        assert_eq!(heap.collect(std::iter::empty()), 2);
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn test_freed_slots_are_reused() {
+        let mut heap = Heap::new();
+        heap.alloc(array(vec![]));
+        let second = heap.alloc(array(vec![]));
+        heap.free(second);
+
+        assert_eq!(heap.alloc(array(vec![Value::Int(5)])), second);
+        assert_eq!(heap.len(), 2);
+    }
+
+    #[test]
+    fn test_threshold_follows_live_size() {
+        let mut heap = Heap::new();
+        assert!(!heap.wants_collection(INITIAL_GC_THRESHOLD));
+        assert!(heap.wants_collection(INITIAL_GC_THRESHOLD + 1));
+
+        let big = heap.alloc(array(vec![Value::Int(0); 999]));
+        heap.collect([Value::Ref(big)].iter());
+        assert!(!heap.wants_collection(1000));
+        assert!(heap.wants_collection(1001));
+    }
+
+    #[test]
+    fn test_from_slots_rebuilds_free_list() {
+        let mut heap = Heap::from(vec![None, Some(array(vec![Value::Int(1)])), None]);
+        assert_eq!(heap.len(), 1);
+        assert_eq!(heap.size(), 2);
+        assert_eq!(heap.alloc(array(vec![])), 0);
+        assert_eq!(heap.alloc(array(vec![])), 2);
+        assert_eq!(heap.alloc(array(vec![])), 3);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::vm::runner::Value;
+use std::collections::VecDeque;
+
+/// A single state change made by an instruction, holding what is needed to undo it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mutation {
+    /// A value was pushed onto the stack.
+    Push,
+    /// This value was popped off the stack.
+    Pop(Value),
+    /// `memory[addr]` was overwritten. `old` is its previous value and
+    /// `old_len` the memory size before the write grew it.
+    MemoryWrite { addr: usize, old: Option<Value>, old_len: usize },
+    /// The heap object at this reference was allocated.
+    Alloc(usize),
+    /// Element or field `index` of the object at `reference` held `old` before being overwritten.
+    ObjectWrite { reference: usize, index: usize, old: Value },
+}
+
+/// Everything one executed instruction changed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepRecord {
+    /// Address of the instruction, which is also the `ip` to go back to.
+    pub ip: usize,
+    pub running: bool,
+    pub fault: Option<String>,
+    pub mutations: Vec<Mutation>,
+}
+
+impl StepRecord {
+    pub fn writes(&self, addr: usize) -> bool {
+        self.mutations.iter().any(|m| matches!(m, Mutation::MemoryWrite { addr: a, .. } if *a == addr))
+    }
+}
+
+/// Journal of executed instructions used to step the VM backwards.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    steps: VecDeque<StepRecord>,
+    limit: Option<usize>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps only the most recent `limit` steps, bounding memory use on long runs.
+    pub fn with_limit(limit: usize) -> Self {
+        Self { steps: VecDeque::new(), limit: Some(limit) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    pub fn steps(&self) -> impl DoubleEndedIterator<Item = &StepRecord> {
+        self.steps.iter()
+    }
+
+    /// Opens the record for the instruction about to run at `ip`.
+    pub fn begin_step(&mut self, ip: usize, running: bool, fault: Option<String>) {
+        if self.limit.is_some_and(|l| self.steps.len() >= l) {
+            self.steps.pop_front();
+        }
+        self.steps.push_back(StepRecord { ip, running, fault, mutations: Vec::new() });
+    }
+
+    /// Adds a mutation to the instruction currently executing.
+    pub fn record(&mut self, mutation: Mutation) {
+        if let Some(step) = self.steps.back_mut() {
+            step.mutations.push(mutation);
+        }
+    }
+
+    pub fn pop_step(&mut self) -> Option<StepRecord> {
+        self.steps.pop_back()
+    }
+
+    /// Values kept to undo earlier steps. The garbage collector treats them
+    /// as roots so stepping back never revives a freed reference.
+    pub fn saved_values(&self) -> impl Iterator<Item = &Value> {
+        self.steps.iter().flat_map(|s| &s.mutations).filter_map(|m| match m {
+            Mutation::Pop(value) => Some(value),
+            Mutation::MemoryWrite { old, .. } => old.as_ref(),
+            Mutation::ObjectWrite { old, .. } => Some(old),
+            Mutation::Push | Mutation::Alloc(_) => None,
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod test_history {
+    use super::*;
+    use crate::vm::assembler::Assembler;
+    use crate::vm::heap::Object;
+    use crate::vm::runner::VirtualMachine;
+
+    const COUNTDOWN: &str = "
+        BIPUSH 3
+        loop:
+        BIPUSH 1
+        SUB
+        DUP
+        STORE 2
+        DUP
+        BIPUSH 0
+        CMP
+        JG loop
+        HALT
+    ";
+
+    fn recording_vm(source: &str) -> VirtualMachine {
+        let code = Assembler::new().assemble(source).unwrap();
+        let mut vm = VirtualMachine::new(code);
+        vm.history = Some(History::new());
+        vm
+    }
+
+    type State = (usize, Vec<Value>, Vec<Value>, bool);
+
+    fn state(vm: &VirtualMachine) -> State {
+        (vm.ip, vm.stack.clone(), vm.memory.clone(), vm.running)
+    }
+
+    #[test]
+    fn test_step_back_retraces_every_state() {
+        let mut vm = recording_vm(COUNTDOWN);
+        let mut states = Vec::new();
+        while vm.running && vm.ip < vm.code.len() {
+            states.push(state(&vm));
+            vm.step().unwrap();
+        }
+
+        while let Some(expected) = states.pop() {
+            assert!(vm.step_back());
+            assert_eq!(state(&vm), expected);
+        }
+        assert!(!vm.step_back(), "Nothing left to undo");
+        assert!(vm.memory.is_empty(), "Memory growth must be undone");
+    }
+
+    #[test]
+    fn test_rewind_to_last_write() {
+        let mut vm = recording_vm(COUNTDOWN);
+        vm.run().unwrap();
+        assert_eq!(vm.memory[2], Value::Int(0));
+
+        // The STORE 2 is at address 6; before its last run memory[2] held 1
+        assert_eq!(vm.rewind_to_last_write(2), Some(6));
+        assert_eq!(vm.ip, 6);
+        assert_eq!(vm.memory[2], Value::Int(1));
+        assert_eq!(vm.stack.last(), Some(&Value::Int(0)));
+
+        assert_eq!(vm.rewind_to_last_write(2), Some(6));
+        assert_eq!(vm.memory[2], Value::Int(2));
+    }
+
+    #[test]
+    fn test_rewind_without_write_leaves_vm_untouched() {
+        let mut vm = recording_vm(COUNTDOWN);
+        vm.run().unwrap();
+        let before = state(&vm);
+
+        assert_eq!(vm.rewind_to_last_write(7), None);
+        assert_eq!(state(&vm), before);
+    }
+
+    #[test]
+    fn test_step_back_clears_fault() {
+        let mut vm = recording_vm("BIPUSH 1\nBIPUSH 0\nDIV");
+        assert!(vm.run().is_err());
+
+        assert!(vm.step_back());
+        assert_eq!(vm.fault, None);
+        assert!(vm.running);
+        assert_eq!(vm.ip, 4);
+        assert_eq!(vm.stack, vec![Value::Int(1), Value::Int(0)]);
+    }
+
+    #[test]
+    fn test_limit_keeps_most_recent_steps() {
+        let mut vm = recording_vm(COUNTDOWN);
+        vm.history = Some(History::with_limit(4));
+        vm.run().unwrap();
+
+        let history = vm.history.as_ref().unwrap();
+        assert_eq!(history.len(), 4);
+        assert_eq!(history.steps().last().unwrap().ip, 20);
+    }
+
+    #[test]
+    fn test_step_back_undoes_array_writes_and_allocation() {
+        let mut vm = recording_vm("BIPUSH 2\nNEWARRAY\nDUP\nBIPUSH 1\nBIPUSH 9\nASTORE\nHALT");
+        vm.run().unwrap();
+        assert_eq!(vm.heap.get(0), Some(&Object::Array(vec![Value::Int(0), Value::Int(9)])));
+
+        // Back over HALT and ASTORE
+        vm.step_back();
+        vm.step_back();
+        assert_eq!(vm.heap.get(0), Some(&Object::Array(vec![Value::Int(0), Value::Int(0)])));
+
+        while vm.step_back() {}
+        assert!(vm.heap.is_empty());
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_without_history_step_back_does_nothing() {
+        let mut vm = VirtualMachine::new(vec![crate::vm::opcodes::op::BIPUSH, 1]);
+        vm.run().unwrap();
+        assert!(!vm.step_back());
+        assert_eq!(vm.stack, vec![Value::Int(1)]);
+    }
+}
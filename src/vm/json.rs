@@ -0,0 +1,248 @@
+use crate::vm::decoder::{decode, DecodeError, Instruction, Operand};
+use crate::vm::heap::{Heap, Object, StructLayout};
+use crate::vm::opcodes::op;
+use crate::vm::runner::{Value, VirtualMachine};
+
+/// Escapes a string for use inside a JSON string literal.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// JSON has no NaN or infinity, so those are written as strings.
+fn float_to_json(val: f64) -> String {
+    if val.is_finite() {
+        format!("{}", val)
+    } else {
+        format!("\"{}\"", val)
+    }
+}
+
+pub fn value_to_json(value: &Value) -> String {
+    match value {
+        Value::Int(v) => format!("{{\"type\": \"Int\", \"value\": {}}}", v),
+        Value::Long(v) => format!("{{\"type\": \"Long\", \"value\": {}}}", v),
+        Value::Bool(b) => format!("{{\"type\": \"Bool\", \"value\": {}}}", b),
+        Value::Ref(r) => format!("{{\"type\": \"Ref\", \"value\": {}}}", r),
+        Value::Float(v) => format!("{{\"type\": \"Float\", \"value\": {}}}", float_to_json(*v)),
+        Value::Char(c) => {
+            format!("{{\"type\": \"Char\", \"value\": \"{}\"}}", escape(&(*c as char).to_string()))
+        }
+    }
+}
+
+fn operand_to_json(operand: &Operand) -> Option<String> {
+    let (kind, value) = match *operand {
+        Operand::None => return None,
+        Operand::Int(v) => ("Int", v.to_string()),
+        Operand::Long(v) => ("Long", v.to_string()),
+        Operand::Float(v) => ("Float", float_to_json(v)),
+        Operand::Address(v) => ("Address", v.to_string()),
+        Operand::Offset(v) => ("Offset", v.to_string()),
+        Operand::Byte(v) => ("Byte", v.to_string()),
+        Operand::Table { .. } => return None,
+    };
+    Some(format!("{{\"kind\": \"{}\", \"value\": {}}}", kind, value))
+}
+
+/// Operands of an instruction in assembler order; a TABLESWITCH lists its
+/// low bound, default target and cases.
+fn operands_to_json(ins: &Instruction, bytecode: &[u8]) -> Vec<String> {
+    match ins.operand {
+        Operand::Table { low, .. } => std::iter::once(Operand::Int(low))
+            .chain(ins.targets(bytecode).into_iter().map(|t| Operand::Address(t as u32)))
+            .filter_map(|operand| operand_to_json(&operand))
+            .collect(),
+        operand => operand_to_json(&operand).into_iter().collect(),
+    }
+}
+
+fn bytes_to_json(bytes: &[u8]) -> String {
+    let items: Vec<String> = bytes.iter().map(|b| b.to_string()).collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// Renders the disassembly as a JSON array with one object per line, so the
+/// output diffs cleanly. Undecodable bytes carry an extra `error` field.
+pub fn disassembly_to_json(bytecode: &[u8]) -> String {
+    let mut items = Vec::new();
+
+    for entry in decode(bytecode) {
+        let item = match entry {
+            Ok(ins) => {
+                let operands = operands_to_json(&ins, bytecode);
+                format!(
+                    "{{\"address\": {}, \"mnemonic\": \"{}\", \"operands\": [{}], \"bytes\": {}}}",
+                    ins.addr,
+                    ins.name(),
+                    operands.join(", "),
+                    bytes_to_json(&bytecode[ins.addr..ins.next_addr()])
+                )
+            }
+            Err(e) => {
+                let (mnemonic, bytes) = match e {
+                    DecodeError::UnknownOpcode { addr, .. } => ("UNKNOWN", &bytecode[addr..addr + 1]),
+                    DecodeError::Truncated { addr, opcode, .. } => {
+                        (op::get_info(opcode).unwrap().name, &bytecode[addr..])
+                    }
+                };
+                format!(
+                    "{{\"address\": {}, \"mnemonic\": \"{}\", \"operands\": [], \"bytes\": {}, \"error\": \"{}\"}}",
+                    e.addr(),
+                    mnemonic,
+                    bytes_to_json(bytes),
+                    escape(&e.to_string())
+                )
+            }
+        };
+        items.push(format!("  {}", item));
+    }
+
+    if items.is_empty() {
+        return "[]\n".to_string();
+    }
+    format!("[\n{}\n]\n", items.join(",\n"))
+}
+
+fn values_to_json(values: &[Value]) -> String {
+    let items: Vec<String> = values.iter().map(value_to_json).collect();
+    format!("[{}]", items.join(", "))
+}
+
+fn heap_to_json(heap: &Heap, structs: &[StructLayout]) -> String {
+    let items: Vec<String> = heap.objects().map(|(reference, object)| match object {
+        Object::Array(values) => format!(
+            "{{\"ref\": {}, \"type\": \"Array\", \"values\": {}}}",
+            reference,
+            values_to_json(values)
+        ),
+        Object::Record { layout, fields } => {
+            let name = match structs.get(*layout) {
+                Some(s) => format!("\"{}\"", escape(&s.name)),
+                None => "null".to_string(),
+            };
+            format!(
+                "{{\"ref\": {}, \"type\": \"Record\", \"struct\": {}, \"values\": {}}}",
+                reference,
+                name,
+                values_to_json(fields)
+            )
+        }
+    }).collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// Renders the machine state after execution. `status` is one of `halted`,
+/// `fault`, `end_of_code` (ran past the last instruction) or `running`.
+pub fn vm_state_to_json(vm: &VirtualMachine) -> String {
+    let status = if vm.fault.is_some() {
+        "fault"
+    } else if !vm.running {
+        "halted"
+    } else if vm.ip >= vm.code.len() {
+        "end_of_code"
+    } else {
+        "running"
+    };
+    let fault = match &vm.fault {
+        Some(msg) => format!("\"{}\"", escape(msg)),
+        None => "null".to_string(),
+    };
+
+    format!(
+        "{{\n  \"status\": \"{}\",\n  \"fault\": {},\n  \"ip\": {},\n  \"stack\": {},\n  \"memory\": {},\n  \"heap\": {}\n}}\n",
+        status,
+        fault,
+        vm.ip,
+        values_to_json(&vm.stack),
+        values_to_json(&vm.memory),
+        heap_to_json(&vm.heap, &vm.structs)
+    )
+}
+
+
+#[cfg(test)]
+mod test_json {
+    use super::*;
+
+    #[test]
+    fn test_escape_special_characters() {
+        assert_eq!(escape("a\"b\\c\nd\u{1}"), "a\\\"b\\\\c\\nd\\u0001");
+    }
+
+    #[test]
+    fn test_value_to_json() {
+        assert_eq!(value_to_json(&Value::Int(-3)), "{\"type\": \"Int\", \"value\": -3}");
+        assert_eq!(value_to_json(&Value::Long(1 << 40)), "{\"type\": \"Long\", \"value\": 1099511627776}");
+        assert_eq!(value_to_json(&Value::Bool(true)), "{\"type\": \"Bool\", \"value\": true}");
+        assert_eq!(value_to_json(&Value::Float(2.5)), "{\"type\": \"Float\", \"value\": 2.5}");
+        assert_eq!(value_to_json(&Value::Float(f64::NAN)), "{\"type\": \"Float\", \"value\": \"NaN\"}");
+        assert_eq!(value_to_json(&Value::Char(b'"')), "{\"type\": \"Char\", \"value\": \"\\\"\"}");
+    }
+
+    #[test]
+    fn test_disassembly_to_json() {
+        let mut bytecode = vec![op::BIPUSH, 10, op::JMP];
+        bytecode.extend(&0u32.to_be_bytes());
+        bytecode.push(0xFF);
+
+        let json = disassembly_to_json(&bytecode);
+        let lines: Vec<&str> = json.lines().collect();
+
+        assert_eq!(lines[0], "[");
+        assert_eq!(
+            lines[1],
+            format!("  {{\"address\": 0, \"mnemonic\": \"BIPUSH\", \"operands\": [{{\"kind\": \"Byte\", \"value\": 10}}], \"bytes\": [{}, 10]}},", op::BIPUSH)
+        );
+        assert!(lines[2].contains("\"mnemonic\": \"JMP\", \"operands\": [{\"kind\": \"Address\", \"value\": 0}]"));
+        assert!(lines[3].contains("\"mnemonic\": \"UNKNOWN\", \"operands\": [], \"bytes\": [255], \"error\": \"Unknown opcode 0xFF at 0x0007\""));
+        assert_eq!(lines[4], "]");
+    }
+
+    #[test]
+    fn test_disassembly_to_json_empty() {
+        assert_eq!(disassembly_to_json(&[]), "[]\n");
+    }
+
+    #[test]
+    fn test_vm_state_to_json_halted() {
+        let mut vm = VirtualMachine::new(vec![op::BIPUSH, 3, op::DUP, op::STORE, 0, 0, 0, 1, op::HALT]);
+        vm.run().unwrap();
+
+        assert_eq!(
+            vm_state_to_json(&vm),
+            "{\n  \"status\": \"halted\",\n  \"fault\": null,\n  \"ip\": 9,\n  \
+             \"stack\": [{\"type\": \"Int\", \"value\": 3}],\n  \
+             \"memory\": [{\"type\": \"Int\", \"value\": 0}, {\"type\": \"Int\", \"value\": 3}],\n  \
+             \"heap\": []\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_vm_state_to_json_fault() {
+        let mut vm = VirtualMachine::new(vec![op::BIPUSH, 1, op::BIPUSH, 0, op::MOD]);
+        assert!(vm.run().is_err());
+
+        let json = vm_state_to_json(&vm);
+        assert!(json.contains("\"status\": \"fault\""));
+        assert!(json.contains("\"fault\": \"Runtime Error: Integer modulo by zero\""));
+    }
+
+    #[test]
+    fn test_vm_state_to_json_end_of_code() {
+        let mut vm = VirtualMachine::new(vec![op::NOP]);
+        vm.run().unwrap();
+        assert!(vm_state_to_json(&vm).contains("\"status\": \"end_of_code\""));
+    }
+}
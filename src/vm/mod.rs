@@ -1,4 +1,14 @@
 pub mod opcodes;
 pub mod runner;
 pub mod disassembler;
-pub mod assembler;
\ No newline at end of file
+pub mod assembler;
+pub mod cfg;
+pub mod decoder;
+pub mod json;
+pub mod trace;
+pub mod profiler;
+pub mod coverage;
+pub mod debug_info;
+pub mod snapshot;
+pub mod history;
+pub mod heap;
@@ -6,6 +6,7 @@ macro_rules! define_instructions {
 
             #[repr(u8)]
             #[derive(Debug, Clone, Copy, PartialEq)]
+            #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
             enum op_enum {
                 $($name,)*
             }
@@ -64,7 +65,7 @@ define_instructions! {
     (DIV,    1),
     (MOD,    1),
 
-    // Comparison
+    // Comparison (pushes CMP_LESS, CMP_EQUAL, CMP_GREATER or CMP_UNORDERED)
     (CMP,    1),
 
     // Control Flow (32-bit Absolute Jumps)
@@ -77,7 +78,152 @@ define_instructions! {
     (JMP,    5),
 
     // I/O
-    (PRINT,  1)
+    (PRINT,  1),
+
+    // Bitwise (Int and Long; shift counts are taken modulo 32 or 64)
+    (AND,    1),
+    (OR,     1),
+    (XOR,    1),
+    (NOT,    1),
+    (SHL,    1),
+    (SHR,    1), // Arithmetic shift, keeps the sign
+    (USHR,   1), // Logical shift, fills with zeros
+
+    // 64-bit Integers
+    (LPUSH,  9), // Opcode + 8-byte i64
+    (I2L,    1),
+    (L2I,    1), // Narrowing follows the overflow mode
+    (L2F,    1),
+
+    // Float Conversions
+    (I2F,    1),
+    (F2I,    2), // Opcode + 1-byte rounding mode
+    (F2L,    2), // Opcode + 1-byte rounding mode
+
+    // Math (integers are promoted to Float; results follow IEEE-754, so e.g.
+    // SQRT of a negative number is NaN)
+    (SQRT,   1),
+    (POW,    1), // base, exponent -> base^exponent
+    (EXP,    1),
+    (LOG,    1), // Natural logarithm
+    (SIN,    1),
+    (COS,    1),
+    (TAN,    1),
+    (ABS,    1), // Keeps integers as integers
+    (FLOOR,  1), // Integers are unchanged
+    (CEIL,   1), // Integers are unchanged
+    (MIN,    1), // Promotes like ADD
+    (MAX,    1), // Promotes like ADD
+
+    // Boolean Comparisons (push a Bool; any comparison with NaN is false except NE)
+    (EQ,     1),
+    (NE,     1),
+    (LT,     1),
+    (LE,     1),
+    (GT,     1),
+    (GE,     1),
+
+    // Boolean Jumps (32-bit Absolute)
+    (JT,     5), // Jump if true
+    (JF,     5), // Jump if false
+
+    // NaN Handling
+    (JU,     5), // Jump if the CMP operands were unordered (NaN)
+    (ISNAN,  1), // Pushes a Bool; integers are never NaN
+
+    // Arrays (heap objects referenced by Value::Ref)
+    (NEWARRAY, 1), // length -> arrayref
+    (ALOAD,    1), // arrayref, index -> value
+    (ASTORE,   1), // arrayref, index, value ->
+    (ALEN,     1), // arrayref -> length
+
+    // Indirect Memory Operations (address taken from the stack)
+    (LOADI,  1), // address -> value
+    (STOREI, 1), // address, value ->
+    (ADDR,   5), // Opcode + 4-byte address; pushes it as an Int (address-of a .data symbol)
+
+    // Garbage Collected Heap
+    (ALLOC,  1), // v1 .. vn, n -> arrayref holding v1 .. vn
+    (GC,     1), // Forces a collection
+
+    // Records (field offsets are resolved by the assembler from .struct layouts)
+    (NEW,      5), // Opcode + 4-byte struct index; -> recordref
+    (GETFIELD, 5), // Opcode + 4-byte field offset; recordref -> value
+    (SETFIELD, 5), // Opcode + 4-byte field offset; recordref, value ->
+
+    // Computed Jumps
+    (JMPI,        1),  // address -> ; jumps to the popped address
+    (TABLESWITCH, 13), // Opcode + 4-byte low + 4-byte default + 4-byte count, followed by
+                       // count 4-byte targets; selector -> ; see TABLESWITCH_HEADER
+
+    // Exceptions (handlers are declared with .catch)
+    (THROW, 1), // value -> ; raises value as an exception
+
+    // PC-relative Jumps (signed 1, 2 or 4-byte displacement from the address of
+    // the jump itself; conditions match the absolute form)
+    (JL8,  2), (JL16,  3), (JL32,  5),
+    (JLE8, 2), (JLE16, 3), (JLE32, 5),
+    (JG8,  2), (JG16,  3), (JG32,  5),
+    (JGE8, 2), (JGE16, 3), (JGE32, 5),
+    (JE8,  2), (JE16,  3), (JE32,  5),
+    (JNE8, 2), (JNE16, 3), (JNE32, 5),
+    (JMP8, 2), (JMP16, 3), (JMP32, 5),
+    (JT8,  2), (JT16,  3), (JT32,  5),
+    (JF8,  2), (JF16,  3), (JF32,  5),
+    (JU8,  2), (JU16,  3), (JU32,  5),
+}
+
+/// Size of the fixed part of TABLESWITCH; the jump table follows it.
+pub const TABLESWITCH_HEADER: usize = 13;
+
+/// Displacement widths of the PC-relative jumps, in bytes.
+pub const DISPLACEMENT_WIDTHS: [u32; 3] = [1, 2, 4];
+
+/// Every absolute jump with its PC-relative forms, one per displacement width.
+const RELATIVE_JUMPS: [(u8, [u8; 3]); 10] = [
+    (op::JL,  [op::JL8,  op::JL16,  op::JL32]),
+    (op::JLE, [op::JLE8, op::JLE16, op::JLE32]),
+    (op::JG,  [op::JG8,  op::JG16,  op::JG32]),
+    (op::JGE, [op::JGE8, op::JGE16, op::JGE32]),
+    (op::JE,  [op::JE8,  op::JE16,  op::JE32]),
+    (op::JNE, [op::JNE8, op::JNE16, op::JNE32]),
+    (op::JMP, [op::JMP8, op::JMP16, op::JMP32]),
+    (op::JT,  [op::JT8,  op::JT16,  op::JT32]),
+    (op::JF,  [op::JF8,  op::JF16,  op::JF32]),
+    (op::JU,  [op::JU8,  op::JU16,  op::JU32]),
+];
+
+/// For a PC-relative jump, returns its absolute form and the width of its
+/// displacement in bytes.
+pub fn relative_jump_info(code: u8) -> Option<(u8, u32)> {
+    RELATIVE_JUMPS.iter().find_map(|&(absolute, forms)| {
+        let index = forms.iter().position(|&f| f == code)?;
+        Some((absolute, DISPLACEMENT_WIDTHS[index]))
+    })
+}
+
+/// Returns the PC-relative form of an absolute jump with a `width`-byte displacement.
+pub fn relative_jump(code: u8, width: u32) -> Option<u8> {
+    let index = DISPLACEMENT_WIDTHS.iter().position(|&w| w == width)?;
+    RELATIVE_JUMPS.iter().find(|(absolute, _)| *absolute == code).map(|(_, forms)| forms[index])
+}
+
+/// Returns true for the conditional jumps, which consume a CMP result or a Bool.
+pub fn is_conditional_jump(code: u8) -> bool {
+    matches!(code, op::JL | op::JLE | op::JG | op::JGE | op::JE | op::JNE | op::JT | op::JF | op::JU)
+        || relative_jump_info(code).is_some_and(|(absolute, _)| is_conditional_jump(absolute))
+}
+
+/// Returns true for every instruction whose operand is a jump target.
+pub fn is_jump(code: u8) -> bool {
+    code == op::JMP || is_conditional_jump(code) || relative_jump_info(code).is_some()
+}
+
+/// Returns true for the instructions that never continue with the next one.
+/// THROW either enters a handler or stops the machine.
+pub fn ends_flow(code: u8) -> bool {
+    matches!(code, op::HALT | op::JMP | op::JMPI | op::TABLESWITCH | op::THROW)
+        || relative_jump_info(code).is_some_and(|(absolute, _)| absolute == op::JMP)
 }
 
 #[macro_export]
@@ -91,16 +237,75 @@ macro_rules! bytecode {
         v
     }};
 
-    (BIPUSH $val:expr $(, $($rest:tt)*)?) => {{
+    (LPUSH $val:expr $(, $($rest:tt)*)?) => {{
         let mut v = Vec::new();
-        v.push(op::BIPUSH);
+        v.push(op::LPUSH);
+        v.extend(&(($val) as i64).to_be_bytes());
+        $( v.extend(bytecode!($($rest)*)); )?
+        v
+    }};
+
+    // Dispatchers for 1-byte instructions
+    (BIPUSH $v:expr $(, $($r:tt)*)?) => { bytecode!(@one BIPUSH, $v, $(, $($r)*)?) };
+    (F2I $v:expr $(, $($r:tt)*)?)    => { bytecode!(@one F2I, $v, $(, $($r)*)?) };
+    (F2L $v:expr $(, $($r:tt)*)?)    => { bytecode!(@one F2L, $v, $(, $($r)*)?) };
+
+    // Relative jumps take a signed displacement
+    (JL8 $v:expr $(, $($r:tt)*)?)    => { bytecode!(@disp8 JL8, $v, $(, $($r)*)?) };
+    (JLE8 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@disp8 JLE8, $v, $(, $($r)*)?) };
+    (JG8 $v:expr $(, $($r:tt)*)?)    => { bytecode!(@disp8 JG8, $v, $(, $($r)*)?) };
+    (JGE8 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@disp8 JGE8, $v, $(, $($r)*)?) };
+    (JE8 $v:expr $(, $($r:tt)*)?)    => { bytecode!(@disp8 JE8, $v, $(, $($r)*)?) };
+    (JNE8 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@disp8 JNE8, $v, $(, $($r)*)?) };
+    (JMP8 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@disp8 JMP8, $v, $(, $($r)*)?) };
+    (JT8 $v:expr $(, $($r:tt)*)?)    => { bytecode!(@disp8 JT8, $v, $(, $($r)*)?) };
+    (JF8 $v:expr $(, $($r:tt)*)?)    => { bytecode!(@disp8 JF8, $v, $(, $($r)*)?) };
+    (JU8 $v:expr $(, $($r:tt)*)?)    => { bytecode!(@disp8 JU8, $v, $(, $($r)*)?) };
+
+    (@disp8 $op:ident, $val:expr, $(, $($rest:tt)*)?) => {{
+        let mut v = Vec::new();
+        v.push(op::$op);
+        v.push((($val) as i8) as u8);
+        $( v.extend(bytecode!($($rest)*)); )?
+        v
+    }};
+
+    (@one $op:ident, $val:expr, $(, $($rest:tt)*)?) => {{
+        let mut v = Vec::new();
+        v.push(op::$op);
         v.push(($val) as u8);
         $( v.extend(bytecode!($($rest)*)); )?
         v
     }};
 
+    // Dispatchers for 2-byte instructions
+    (JL16 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@two JL16, $v, $(, $($r)*)?) };
+    (JLE16 $v:expr $(, $($r:tt)*)?)  => { bytecode!(@two JLE16, $v, $(, $($r)*)?) };
+    (JG16 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@two JG16, $v, $(, $($r)*)?) };
+    (JGE16 $v:expr $(, $($r:tt)*)?)  => { bytecode!(@two JGE16, $v, $(, $($r)*)?) };
+    (JE16 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@two JE16, $v, $(, $($r)*)?) };
+    (JNE16 $v:expr $(, $($r:tt)*)?)  => { bytecode!(@two JNE16, $v, $(, $($r)*)?) };
+    (JMP16 $v:expr $(, $($r:tt)*)?)  => { bytecode!(@two JMP16, $v, $(, $($r)*)?) };
+    (JT16 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@two JT16, $v, $(, $($r)*)?) };
+    (JF16 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@two JF16, $v, $(, $($r)*)?) };
+    (JU16 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@two JU16, $v, $(, $($r)*)?) };
+
+    (@two $op:ident, $val:expr, $(, $($rest:tt)*)?) => {{
+        let mut v = Vec::new();
+        v.push(op::$op);
+        v.extend(&(($val) as i16).to_be_bytes());
+        $( v.extend(bytecode!($($rest)*)); )?
+        v
+    }};
+
     // Dispatchers for 4-byte instructions
     (IPUSH $v:expr $(, $($r:tt)*)?) => { bytecode!(@four IPUSH, $v, $(, $($r)*)?) };
+    (STORE $v:expr $(, $($r:tt)*)?) => { bytecode!(@four STORE, $v, $(, $($r)*)?) };
+    (LOAD $v:expr $(, $($r:tt)*)?)  => { bytecode!(@four LOAD, $v, $(, $($r)*)?) };
+    (ADDR $v:expr $(, $($r:tt)*)?)  => { bytecode!(@four ADDR, $v, $(, $($r)*)?) };
+    (NEW $v:expr $(, $($r:tt)*)?)   => { bytecode!(@four NEW, $v, $(, $($r)*)?) };
+    (GETFIELD $v:expr $(, $($r:tt)*)?) => { bytecode!(@four GETFIELD, $v, $(, $($r)*)?) };
+    (SETFIELD $v:expr $(, $($r:tt)*)?) => { bytecode!(@four SETFIELD, $v, $(, $($r)*)?) };
     (JL $v:expr $(, $($r:tt)*)?)    => { bytecode!(@four JL, $v, $(, $($r)*)?) };
     (JLE $v:expr $(, $($r:tt)*)?)   => { bytecode!(@four JLE, $v, $(, $($r)*)?) };
     (JG $v:expr $(, $($r:tt)*)?)    => { bytecode!(@four JG, $v, $(, $($r)*)?) };
@@ -108,6 +313,35 @@ macro_rules! bytecode {
     (JE $v:expr $(, $($r:tt)*)?)    => { bytecode!(@four JE, $v, $(, $($r)*)?) };
     (JNE $v:expr $(, $($r:tt)*)?)   => { bytecode!(@four JNE, $v, $(, $($r)*)?) };
     (JMP $v:expr $(, $($r:tt)*)?)   => { bytecode!(@four JMP, $v, $(, $($r)*)?) };
+    (JT $v:expr $(, $($r:tt)*)?)    => { bytecode!(@four JT, $v, $(, $($r)*)?) };
+    (JF $v:expr $(, $($r:tt)*)?)    => { bytecode!(@four JF, $v, $(, $($r)*)?) };
+    (JU $v:expr $(, $($r:tt)*)?)    => { bytecode!(@four JU, $v, $(, $($r)*)?) };
+
+    // TABLESWITCH low, default, [target, ...]
+    (TABLESWITCH $low:expr, $default:expr, [$($target:expr),* $(,)?] $(, $($rest:tt)*)?) => {{
+        let targets: Vec<u32> = vec![$(($target) as u32),*];
+        let mut v = Vec::new();
+        v.push(op::TABLESWITCH);
+        v.extend(&(($low) as i32).to_be_bytes());
+        v.extend(&(($default) as u32).to_be_bytes());
+        v.extend(&(targets.len() as u32).to_be_bytes());
+        for target in targets {
+            v.extend(&target.to_be_bytes());
+        }
+        $( v.extend(bytecode!($($rest)*)); )?
+        v
+    }};
+
+    (JL32 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@four JL32, $v, $(, $($r)*)?) };
+    (JLE32 $v:expr $(, $($r:tt)*)?)  => { bytecode!(@four JLE32, $v, $(, $($r)*)?) };
+    (JG32 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@four JG32, $v, $(, $($r)*)?) };
+    (JGE32 $v:expr $(, $($r:tt)*)?)  => { bytecode!(@four JGE32, $v, $(, $($r)*)?) };
+    (JE32 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@four JE32, $v, $(, $($r)*)?) };
+    (JNE32 $v:expr $(, $($r:tt)*)?)  => { bytecode!(@four JNE32, $v, $(, $($r)*)?) };
+    (JMP32 $v:expr $(, $($r:tt)*)?)  => { bytecode!(@four JMP32, $v, $(, $($r)*)?) };
+    (JT32 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@four JT32, $v, $(, $($r)*)?) };
+    (JF32 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@four JF32, $v, $(, $($r)*)?) };
+    (JU32 $v:expr $(, $($r:tt)*)?)   => { bytecode!(@four JU32, $v, $(, $($r)*)?) };
 
     (@four $op:ident, $val:expr, $(, $($rest:tt)*)?) => {{
         let mut v = Vec::new();
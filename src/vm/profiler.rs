@@ -0,0 +1,177 @@
+use crate::vm::disassembler::{format_instruction, label_names};
+use crate::vm::opcodes::{is_conditional_jump, op};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BranchStats {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+impl BranchStats {
+    /// Fraction of executions that jumped, between 0.0 and 1.0.
+    pub fn taken_ratio(&self) -> f64 {
+        let total = self.taken + self.not_taken;
+        if total == 0 { 0.0 } else { self.taken as f64 / total as f64 }
+    }
+}
+
+/// Counts executed instructions per opcode and per address, and records
+/// which way every conditional jump went.
+#[derive(Debug, Default, Clone)]
+pub struct Profiler {
+    pub total: u64,
+    pub opcode_counts: BTreeMap<u8, u64>,
+    pub address_counts: BTreeMap<usize, u64>,
+    pub branches: BTreeMap<usize, BranchStats>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records one executed instruction. `next_ip` is the instruction pointer
+    /// after it ran, which tells whether a conditional jump was taken.
+    pub fn record(&mut self, addr: usize, opcode: u8, next_ip: usize) {
+        self.total += 1;
+        *self.opcode_counts.entry(opcode).or_insert(0) += 1;
+        *self.address_counts.entry(addr).or_insert(0) += 1;
+
+        if is_conditional_jump(opcode) {
+            let size = op::get_info(opcode).unwrap().size as usize;
+            let stats = self.branches.entry(addr).or_default();
+            // A jump whose target is the next instruction counts as not taken
+            if next_ip != addr + size {
+                stats.taken += 1;
+            } else {
+                stats.not_taken += 1;
+            }
+        }
+    }
+
+    /// Builds a text report: counts per opcode followed by the `top` hottest
+    /// addresses annotated with their disassembly and enclosing label.
+    pub fn report(&self, code: &[u8], labels: Option<&HashMap<String, u32>>, top: usize) -> String {
+        let names = label_names(labels);
+        let mut out = String::from("--- PROFILE ---\n");
+        out.push_str(&format!("Instructions executed: {}\n\n", self.total));
+
+        let mut opcodes: Vec<(&u8, &u64)> = self.opcode_counts.iter().collect();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+        out.push_str(&format!("{:<10} {:>10} {:>7}\n", "Opcode", "Count", "%"));
+        for (&opcode, &count) in opcodes {
+            let name = op::get_info(opcode).map(|i| i.name).unwrap_or("UNKNOWN");
+            out.push_str(&format!("{:<10} {:>10} {:>6.1}%\n", name, count, self.percent(count)));
+        }
+
+        let mut hot: Vec<(&usize, &u64)> = self.address_counts.iter().collect();
+        hot.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+        out.push_str(&format!("\nHot spots (top {}):\n", top));
+        out.push_str(&format!("{:>10} {:>7}  {:<4}  {:<16} {:<20} {}\n", "Count", "%", "Addr", "Location", "Instruction", "Branch"));
+        for (&addr, &count) in hot.into_iter().take(top) {
+            let branch = match self.branches.get(&addr) {
+                Some(b) => format!(
+                    "taken {} / not taken {} ({:.1}% taken)",
+                    b.taken, b.not_taken, b.taken_ratio() * 100.0
+                ),
+                None => String::new(),
+            };
+            let line = format!(
+                "{:>10} {:>6.1}%  {:04X}  {:<16} {:<20} {}",
+                count,
+                self.percent(count),
+                addr,
+                location(&names, addr),
+                format_instruction(code, addr, &names),
+                branch
+            );
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        if self.total == 0 { 0.0 } else { count as f64 * 100.0 / self.total as f64 }
+    }
+}
+
+/// Names an address relative to the closest label at or before it, e.g. `loop+3`.
+fn location(names: &HashMap<usize, String>, addr: usize) -> String {
+    match names.iter().filter(|(a, _)| **a <= addr).max_by_key(|(a, _)| **a) {
+        Some((&base, name)) if base == addr => name.clone(),
+        Some((&base, name)) => format!("{}+{}", name, addr - base),
+        None => String::new(),
+    }
+}
+
+
+#[cfg(test)]
+mod test_profiler {
+    use super::*;
+    use crate::vm::assembler::Assembler;
+    use crate::vm::runner::VirtualMachine;
+
+    fn profile(source: &str) -> (Profiler, Vec<u8>, HashMap<String, u32>) {
+        let mut assembler = Assembler::new();
+        let code = assembler.assemble(source).expect("Assembly failed");
+        let mut vm = VirtualMachine::new(code.clone());
+        vm.profiler = Some(Profiler::new());
+        vm.run().unwrap();
+        (vm.profiler.unwrap(), code, assembler.labels().clone())
+    }
+
+    const COUNTDOWN: &str = "
+        BIPUSH 3
+        loop:
+        BIPUSH 1
+        SUB
+        DUP
+        BIPUSH 0
+        CMP
+        JG loop
+        HALT
+    ";
+
+    #[test]
+    fn test_counts_per_opcode_and_address() {
+        let (profiler, _, _) = profile(COUNTDOWN);
+
+        // 1 BIPUSH + 3 iterations of 6 instructions + HALT
+        assert_eq!(profiler.total, 20);
+        assert_eq!(profiler.opcode_counts[&op::BIPUSH], 7);
+        assert_eq!(profiler.opcode_counts[&op::JG], 3);
+        assert_eq!(profiler.address_counts[&0], 1);
+        assert_eq!(profiler.address_counts[&2], 3);
+    }
+
+    #[test]
+    fn test_branch_direction_is_tracked() {
+        let (profiler, _, _) = profile(COUNTDOWN);
+
+        let jg = profiler.branches[&9];
+        assert_eq!(jg, BranchStats { taken: 2, not_taken: 1 });
+        assert!((jg.taken_ratio() - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_report_annotates_hot_spots() {
+        let (profiler, code, labels) = profile(COUNTDOWN);
+        let report = profiler.report(&code, Some(&labels), 10);
+
+        assert!(report.starts_with("--- PROFILE ---\nInstructions executed: 20\n"));
+        assert!(report.contains("BIPUSH              7   35.0%"));
+        assert!(report.contains("loop "));
+        assert!(report.contains("JG loop"));
+        assert!(report.contains("taken 2 / not taken 1 (66.7% taken)"));
+        assert!(report.contains("loop+7"));
+    }
+
+    #[test]
+    fn test_location_without_labels_is_blank() {
+        assert_eq!(location(&HashMap::new(), 5), "");
+    }
+}
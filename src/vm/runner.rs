@@ -1,4 +1,10 @@
-use crate::vm::opcodes::op;
+use crate::vm::debug_info::DebugInfo;
+use crate::vm::heap::{Heap, Object, StructLayout};
+use crate::vm::history::{History, Mutation};
+use crate::vm::opcodes::{op, relative_jump_info};
+use crate::vm::profiler::Profiler;
+use crate::vm::trace::Tracer;
+use std::cmp::Ordering;
 
 macro_rules! read_bytes {
     ($self:ident, $ty:ty) => {{
@@ -6,8 +12,9 @@ macro_rules! read_bytes {
         let start = $self.ip;
         let end = $self.ip + size;
         
-        let bytes = &$self.code[start..end];
-        let value = <$ty>::from_be_bytes(bytes.try_into().expect("Bytecode ended prematurely"));
+        let bytes = $self.code.get(start..end)
+            .ok_or_else(|| Fault::Fatal("Runtime Error: Bytecode ended prematurely".to_string()))?;
+        let value = <$ty>::from_be_bytes(bytes.try_into().unwrap());
         
         $self.ip += size; // Automatically advance the instruction pointer
         value
@@ -19,29 +26,191 @@ macro_rules! read_bytes {
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Value{
     Int(i32),
+    Long(i64),
     Float(f64),
     Char(u8),
+    Bool(bool),
+    /// Reference to an object in `VirtualMachine::heap`
+    Ref(usize),
+}
+
+
+/// What integer arithmetic does when a result does not fit in its type
+/// (i32 for Int, i64 for Long).
+/// The behaviour is the same in debug and release builds.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum OverflowMode {
+    /// Two's complement wrap-around, e.g. i32::MAX + 1 == i32::MIN
+    #[default]
+    Wrapping,
+    /// Clamp to the type's MIN or MAX
+    Saturating,
+    /// Raise an arithmetic fault, a runtime error unless a handler catches it
+    Trapping,
+}
+
+/// How F2I and F2L round a Float, encoded in their operand byte.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// Toward zero
+    Truncate = 0,
+    /// Toward negative infinity
+    Floor = 1,
+    /// Toward positive infinity
+    Ceil = 2,
+    /// To the nearest integer, halfway cases away from zero
+    Round = 3,
+}
+
+impl RoundingMode {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(RoundingMode::Truncate),
+            1 => Some(RoundingMode::Floor),
+            2 => Some(RoundingMode::Ceil),
+            3 => Some(RoundingMode::Round),
+            _ => None,
+        }
+    }
+
+    /// Parses the assembler spelling: trunc, floor, ceil or round
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "trunc" => Some(RoundingMode::Truncate),
+            "floor" => Some(RoundingMode::Floor),
+            "ceil" => Some(RoundingMode::Ceil),
+            "round" => Some(RoundingMode::Round),
+            _ => None,
+        }
+    }
+
+    pub fn apply(self, v: f64) -> f64 {
+        match self {
+            RoundingMode::Truncate => v.trunc(),
+            RoundingMode::Floor => v.floor(),
+            RoundingMode::Ceil => v.ceil(),
+            RoundingMode::Round => v.round(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct VmConfig {
+    pub overflow: OverflowMode,
+    /// Raise an arithmetic fault when an arithmetic or math instruction
+    /// produces NaN, instead of letting it propagate
+    pub trap_nan: bool,
+    /// Maximum heap size in slots (see `Object::slots`). An allocation that
+    /// does not fit even after a collection is a runtime error.
+    pub heap_limit: Option<usize>,
+}
+
+// Results pushed by CMP. Comparisons follow IEEE-754: when either operand is
+// NaN the operands are unordered, so JL, JLE, JG, JGE and JE do not jump,
+// JNE does, and JU jumps only in this case.
+pub const CMP_LESS: i32 = -1;
+pub const CMP_EQUAL: i32 = 0;
+pub const CMP_GREATER: i32 = 1;
+pub const CMP_UNORDERED: i32 = -2;
+
+// Exceptions pushed for recoverable faults caught by a handler. THROW passes
+// its operand on unchanged instead.
+pub const EXC_BOUNDS: i32 = -1;
+pub const EXC_TYPE: i32 = -2;
+pub const EXC_DIVISION_BY_ZERO: i32 = -3;
+pub const EXC_ARITHMETIC: i32 = -4;
+
+/// Error raised by an instruction. Type, division by zero, bounds and
+/// arithmetic faults and thrown values can be caught by a `.catch` handler,
+/// fatal faults such as stack underflow, malformed bytecode or heap exhaustion
+/// always stop the machine.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+    Type(String),
+    DivisionByZero(String),
+    /// An array index, record field or memory address out of range
+    Bounds(String),
+    /// An integer overflow or NaN trapped by the overflow mode or `trap_nan`
+    Arithmetic(String),
+    /// A value raised by THROW
+    Thrown(Value),
+    Fatal(String),
+}
+
+impl Fault {
+    /// The value pushed for a handler, or None when the fault cannot be caught
+    pub fn exception(&self) -> Option<Value> {
+        match self {
+            Fault::Type(_) => Some(Value::Int(EXC_TYPE)),
+            Fault::DivisionByZero(_) => Some(Value::Int(EXC_DIVISION_BY_ZERO)),
+            Fault::Bounds(_) => Some(Value::Int(EXC_BOUNDS)),
+            Fault::Arithmetic(_) => Some(Value::Int(EXC_ARITHMETIC)),
+            Fault::Thrown(value) => Some(*value),
+            Fault::Fatal(_) => None,
+        }
+    }
+}
+
+/// Entry of the exception handler table, declared with `.catch`. An exception
+/// raised by an instruction in `start..end` cuts the stack back to `depth`
+/// values, pushes the exception and continues at `handler`.
+/// When ranges nest, the innermost one covering the instruction is used.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ExceptionHandler {
+    pub start: usize,
+    pub end: usize,
+    pub handler: usize,
+    pub depth: usize,
 }
 
 
 pub struct VirtualMachine{
     pub code       : Vec<u8>,
+    pub config     : VmConfig,
     pub ip         : usize,
     pub stack      : Vec<Value>,
     pub memory     : Vec<Value>,
     pub constants  : Vec<Value>,
-    pub running    : bool
+    pub heap       : Heap,
+    /// Record layouts from the assembler's `.struct` directives, indexed by NEW
+    pub structs    : Vec<StructLayout>,
+    /// Searched in order, so inner handlers must come before outer ones
+    pub handlers   : Vec<ExceptionHandler>,
+    pub running    : bool,
+    /// Message of the runtime error that stopped the machine, if any
+    pub fault      : Option<String>,
+    /// When set, every executed instruction is logged to the tracer
+    pub tracer     : Option<Tracer>,
+    /// When set, executed instructions are counted per opcode and address
+    pub profiler   : Option<Profiler>,
+    /// Source map used to name the offending line in runtime errors
+    pub debug_info : Option<DebugInfo>,
+    /// When set, every state change is journaled so execution can be reversed
+    pub history    : Option<History>
 }
 
 impl VirtualMachine{
     pub fn new(code : Vec<u8>) -> Self{
+        Self::with_config(code, VmConfig::default())
+    }
+
+    pub fn with_config(code : Vec<u8>, config : VmConfig) -> Self{
         Self{
             code,
+            config,
             ip: 0,
             stack:  Vec::with_capacity(1024),
             memory: Vec::new(),
             constants: Vec::new(),
-            running: true
+            heap: Heap::new(),
+            structs: Vec::new(),
+            handlers: Vec::new(),
+            running: true,
+            fault: None,
+            tracer: None,
+            profiler: None,
+            debug_info: None,
+            history: None
         }
     }
 
@@ -57,229 +226,495 @@ impl VirtualMachine{
     /// Add an item in the stack
     pub fn push(&mut self, value : Value) {
         self.stack.push(value);
+        self.journal(Mutation::Push);
     }
     
     /// Removes and returns item from stack
     pub fn pop(&mut self) -> Value{
-        self.stack.pop().expect("Stack underflow!")
+        self.try_pop().expect("Stack underflow!")
+    }
+
+    /// Like `pop`, but reports an empty stack as a runtime error
+    fn try_pop(&mut self) -> Result<Value, Fault> {
+        let value = self.stack.pop().ok_or_else(|| Fault::Fatal("Stack underflow!".to_string()))?;
+        self.journal(Mutation::Pop(value));
+        Ok(value)
+    }
+
+    /// Writes a value to memory, growing it with zeros when needed
+    fn write_memory(&mut self, address: usize, value: Value) {
+        let old_len = self.memory.len();
+        let old = self.memory.get(address).copied();
+        if address >= self.memory.len() {
+            self.memory.resize(address + 1, Value::Int(0));
+        }
+        self.memory[address] = value;
+        self.journal(Mutation::MemoryWrite { addr: address, old, old_len });
+    }
+
+    fn journal(&mut self, mutation: Mutation) {
+        if let Some(history) = self.history.as_mut() {
+            history.record(mutation);
+        }
+    }
+
+    /// Undoes the most recently executed instruction. Returns false when
+    /// there is no recorded history left to rewind.
+    pub fn step_back(&mut self) -> bool {
+        let record = match self.history.as_mut().and_then(|h| h.pop_step()) {
+            Some(r) => r,
+            None => return false,
+        };
+
+        for mutation in record.mutations.into_iter().rev() {
+            match mutation {
+                Mutation::Push => { self.stack.pop(); }
+                Mutation::Pop(value) => self.stack.push(value),
+                Mutation::MemoryWrite { addr, old, old_len } => {
+                    if let Some(value) = old {
+                        self.memory[addr] = value;
+                    }
+                    self.memory.truncate(old_len);
+                }
+                Mutation::Alloc(reference) => { self.heap.free(reference); }
+                Mutation::ObjectWrite { reference, index, old } => {
+                    if let Some(object) = self.heap.get_mut(reference) {
+                        object.values_mut()[index] = old;
+                    }
+                }
+            }
+        }
+        self.ip = record.ip;
+        self.running = record.running;
+        self.fault = record.fault;
+        true
+    }
+
+    /// Rewinds to just before the most recent instruction that wrote
+    /// `memory[address]` and returns its address. Returns None, leaving the
+    /// machine untouched, if no recorded instruction wrote there.
+    pub fn rewind_to_last_write(&mut self, address: usize) -> Option<usize> {
+        let history = self.history.as_ref()?;
+        let write = history.steps().rev().position(|s| s.writes(address))?;
+
+        // Undo every later instruction and then the write itself
+        for _ in 0..=write {
+            self.step_back();
+        }
+        Some(self.ip)
+    }
+
+    /// Picks the result of an integer operation according to the overflow mode.
+    /// `checked` is None when the exact result does not fit.
+    fn int_op<T>(&self, name: &str, checked: Option<T>, wrapping: T, saturating: T) -> Result<T, Fault> {
+        match self.config.overflow {
+            OverflowMode::Wrapping => Ok(wrapping),
+            OverflowMode::Saturating => Ok(checked.unwrap_or(saturating)),
+            OverflowMode::Trapping => {
+                checked.ok_or_else(|| Fault::Arithmetic(format!("Runtime Error: Integer overflow in {}", name)))
+            }
+        }
+    }
+
+    /// Rounds a Float and converts it to an integer type whose range is
+    /// [-limit, limit). NaN becomes 0 and out-of-range values saturate, unless
+    /// the overflow mode is Trapping, in which case both are runtime errors.
+    fn float_to_int<T>(&self, name: &str, v: f64, mode: u8, limit: f64, cast: fn(f64) -> T) -> Result<T, Fault> {
+        let mode = RoundingMode::from_byte(mode)
+            .ok_or_else(|| Fault::Fatal(format!("Runtime Error: Invalid rounding mode {} in {}", mode, name)))?;
+        let rounded = mode.apply(v);
+        if rounded.is_nan() && self.config.overflow == OverflowMode::Trapping {
+            return Err(Fault::Arithmetic(format!("Runtime Error: Cannot convert NaN in {}", name)));
+        }
+
+        // `as` saturates and maps NaN to 0, so it is both the wrapping and saturating result
+        let checked = (rounded >= -limit && rounded < limit).then(|| cast(rounded));
+        self.int_op(name, checked, cast(rounded), cast(rounded))
+    }
+
+    /// Widens a mixed pair involving a Long: an Int becomes a Long, and a Long
+    /// next to a Float becomes a Float. Other pairs are returned unchanged.
+    fn promote(a: Value, b: Value) -> (Value, Value) {
+        match (a, b) {
+            (Value::Int(v1), Value::Long(v2)) => (Value::Long(v1 as i64), Value::Long(v2)),
+            (Value::Long(v1), Value::Int(v2)) => (Value::Long(v1), Value::Long(v2 as i64)),
+            (Value::Long(v1), Value::Float(v2)) => (Value::Float(v1 as f64), Value::Float(v2)),
+            (Value::Float(v1), Value::Long(v2)) => (Value::Float(v1), Value::Float(v2 as f64)),
+            other => other,
+        }
     }
 
     fn compare_f64(&self, v1: f64, v2: f64) -> i32 {
         if v1 < v2 {
-            -1
+            CMP_LESS
         } else if v1 > v2 {
-            1
+            CMP_GREATER
         } else if v1 == v2 {
-            0
+            CMP_EQUAL
         } else {
             // This happens if v1 or v2 is NaN
-            // We return -2 to signal "Unordered/Error"
-            -2 
+            CMP_UNORDERED
+        }
+    }
+
+    /// Whether any operand the arithmetic or math instruction `code` is about
+    /// to pop is already NaN. Such a NaN is propagated, not produced.
+    fn has_nan_operand(&self, code: u8) -> bool {
+        let arity = nan_arity(code).unwrap_or(0);
+        self.stack.iter().rev().take(arity).any(|v| matches!(v, Value::Float(f) if f.is_nan()))
+    }
+
+    /// With `trap_nan`, rejects a NaN left on the stack by an instruction that
+    /// computes a value from operands that were not NaN
+    fn check_nan(&self, code: u8, nan_operand: bool) -> Result<(), Fault> {
+        let computes = nan_arity(code).is_some() && !nan_operand;
+        match self.stack.last() {
+            Some(Value::Float(v)) if self.config.trap_nan && computes && v.is_nan() => {
+                Err(Fault::Arithmetic(format!("Runtime Error: {} produced NaN", op::get_info(code).unwrap().name)))
+            }
+            _ => Ok(()),
         }
     }
     
-    /// Executes the virtual machine
+    /// Executes the virtual machine, panicking on runtime errors
     pub fn execute(&mut self){
-        let mut cur_op : u8;
-        while self.ip < self.code.len() && self.running {
-            cur_op = self.fetch();
-
-            match cur_op {
-                op::NOP => continue,
-                op::HALT => {self.running = false},
-                op::IPUSH => self.handle_ipush(),
-                op::FPUSH => self.handle_fpush(),
-                op::POP => self.handle_pop(),
-                op::BIPUSH => self.handle_bipush(),
-                op::SWP => self.handle_swp(),
-                op::DUP => self.handle_dup(),
-                op::NEG => self.handle_neg(),
-                op::ADD => self.handle_add(),
-                op::SUB => self.handle_sub(),
-                op::MUL => self.handle_mul(),
-                op::DIV => self.handle_div(),
-                op::MOD => self.handle_mod(),
-                op::CMP => self.handle_cmp(),
-                op::JL  => self.handle_jl(),
-                op::JLE  => self.handle_jle(),
-                op::JG  => self.handle_jg(),
-                op::JGE  => self.handle_jge(),
-                op::JE  => self.handle_je(),
-                op::JNE  => self.handle_jne(),
-                op::JMP  => self.handle_jmp(),
-                op::STORE => self.handle_store(),
-                op::LOAD => self.handle_load(),
-                op::PRINT => self.handle_print(),
-                _ => panic!("Unknown opcode: {}", cur_op),
+        if let Err(e) = self.run() {
+            panic!("{}", e);
+        }
+    }
+
+    /// Executes the virtual machine until it halts, runs off the end of the
+    /// code or hits a runtime error. Errors are also recorded in `fault`.
+    pub fn run(&mut self) -> Result<(), String> {
+        self.run_for(usize::MAX).map(|_| ())
+    }
+
+    /// Like `run`, but pauses after at most `max_steps` instructions so the
+    /// machine can be inspected or snapshotted. Returns the steps executed.
+    pub fn run_for(&mut self, max_steps: usize) -> Result<usize, String> {
+        let mut steps = 0;
+        while self.ip < self.code.len() && self.running && steps < max_steps {
+            if let Err(e) = self.step() {
+                self.running = false;
+                self.fault = Some(e.clone());
+                return Err(e);
             }
+            steps += 1;
         }
+        Ok(steps)
     }
 
+    /// Executes a single instruction
+    pub fn step(&mut self) -> Result<(), String> {
+        if self.ip >= self.code.len() {
+            return Err(format!("Runtime Error: Instruction pointer out of bounds: {}", self.ip));
+        }
+
+        let addr = self.ip;
+        let before = self.stack.last().copied();
+        let nan_operand = self.config.trap_nan && self.has_nan_operand(self.code[addr]);
+        if let Some(history) = self.history.as_mut() {
+            history.begin_step(addr, self.running, self.fault.clone());
+        }
+        let result = self.dispatch()
+            .and_then(|()| self.check_nan(self.code[addr], nan_operand))
+            .or_else(|fault| self.raise(addr, fault))
+            .map_err(|fault| self.fault_message(fault))
+            .map_err(|e| match self.debug_info.as_ref().and_then(|d| d.describe(addr)) {
+                Some(location) => format!("{} at {}", e, location),
+                None => e,
+            });
+
+        if let Some(profiler) = self.profiler.as_mut() {
+            profiler.record(addr, self.code[addr], self.ip);
+        }
+        if let Some(tracer) = self.tracer.as_mut()
+            && tracer.traces(addr)
+        {
+            tracer.record(&self.code, addr, before, self.stack.last().copied(), result.as_ref().err());
+        }
+        result
+    }
 
-    pub fn handle_ipush(&mut self){
-        let start = self.ip;
-        let end = self.ip + 4;
-        let bytes = &self.code[start..end];
+    /// Fetches the next opcode and runs its handler
+    fn dispatch(&mut self) -> Result<(), Fault> {
+        let cur_op = self.fetch();
+
+        match cur_op {
+            op::NOP => Ok(()),
+            op::HALT => {self.running = false; Ok(())},
+            op::IPUSH => self.handle_ipush(),
+            op::FPUSH => self.handle_fpush(),
+            op::POP => self.handle_pop(),
+            op::BIPUSH => self.handle_bipush(),
+            op::SWP => self.handle_swp(),
+            op::DUP => self.handle_dup(),
+            op::NEG => self.handle_neg(),
+            op::ADD => self.handle_add(),
+            op::SUB => self.handle_sub(),
+            op::MUL => self.handle_mul(),
+            op::DIV => self.handle_div(),
+            op::MOD => self.handle_mod(),
+            op::CMP => self.handle_cmp(),
+            op::JL | op::JLE | op::JG | op::JGE | op::JE | op::JNE | op::JMP => self.handle_jump(cur_op),
+            op::STORE => self.handle_store(),
+            op::LOAD => self.handle_load(),
+            op::PRINT => self.handle_print(),
+            op::AND => self.handle_and(),
+            op::OR => self.handle_or(),
+            op::XOR => self.handle_xor(),
+            op::NOT => self.handle_not(),
+            op::SHL => self.handle_shl(),
+            op::SHR => self.handle_shr(),
+            op::USHR => self.handle_ushr(),
+            op::LPUSH => self.handle_lpush(),
+            op::I2L => self.handle_i2l(),
+            op::L2I => self.handle_l2i(),
+            op::L2F => self.handle_l2f(),
+            op::I2F => self.handle_i2f(),
+            op::F2I => self.handle_f2i(),
+            op::F2L => self.handle_f2l(),
+            op::SQRT => self.float_unary("SQRT", f64::sqrt),
+            op::POW => self.handle_pow(),
+            op::EXP => self.float_unary("EXP", f64::exp),
+            op::LOG => self.float_unary("LOG", f64::ln),
+            op::SIN => self.float_unary("SIN", f64::sin),
+            op::COS => self.float_unary("COS", f64::cos),
+            op::TAN => self.float_unary("TAN", f64::tan),
+            op::ABS => self.handle_abs(),
+            op::FLOOR => self.handle_round_float("FLOOR", f64::floor),
+            op::CEIL => self.handle_round_float("CEIL", f64::ceil),
+            op::MIN => self.handle_min_max("MIN", false),
+            op::MAX => self.handle_min_max("MAX", true),
+            op::EQ => self.handle_compare("EQ", |o| o == Some(Ordering::Equal)),
+            op::NE => self.handle_compare("NE", |o| o != Some(Ordering::Equal)),
+            op::LT => self.handle_compare("LT", |o| o == Some(Ordering::Less)),
+            op::LE => self.handle_compare("LE", |o| matches!(o, Some(Ordering::Less | Ordering::Equal))),
+            op::GT => self.handle_compare("GT", |o| o == Some(Ordering::Greater)),
+            op::GE => self.handle_compare("GE", |o| matches!(o, Some(Ordering::Greater | Ordering::Equal))),
+            op::JT | op::JF | op::JU => self.handle_jump(cur_op),
+            op::ISNAN => self.handle_isnan(),
+            op::NEWARRAY => self.handle_newarray(),
+            op::ALOAD => self.handle_aload(),
+            op::ASTORE => self.handle_astore(),
+            op::ALEN => self.handle_alen(),
+            op::LOADI => self.handle_loadi(),
+            op::STOREI => self.handle_storei(),
+            op::ADDR => self.handle_addr(),
+            op::ALLOC => self.handle_alloc(),
+            op::GC => { self.collect_garbage(); Ok(()) },
+            op::NEW => self.handle_new(),
+            op::GETFIELD => self.handle_getfield(),
+            op::SETFIELD => self.handle_setfield(),
+            op::JMPI => self.handle_jmpi(),
+            op::TABLESWITCH => self.handle_tableswitch(),
+            op::THROW => self.handle_throw(),
+            _ if relative_jump_info(cur_op).is_some() => self.handle_relative_jump(cur_op),
+            _ => Err(Fault::Fatal(format!("Unknown opcode: {}", cur_op))),
+        }
+    }
 
-        // Convert bytes to i32 (using Big Endian)
-        let value = i32::from_be_bytes(bytes.try_into().expect("Bytecode ended prematurely"));
 
-        // Move the IP forward by 4
-        self.ip += 4;
+    pub fn handle_ipush(&mut self) -> Result<(), Fault> {
+        // Convert 4 bytes to i32 (using Big Endian) and move the IP forward
+        let value = read_bytes!(self, i32);
 
         self.push(Value::Int(value));
+        Ok(())
     }
 
-    pub fn handle_fpush(&mut self) {
-        let start = self.ip;
-        let end = self.ip + 8;
-        let bytes = &self.code[start..end];
+    pub fn handle_fpush(&mut self) -> Result<(), Fault> {
+        // Convert 8 bytes to f64 (using Big Endian) and move the IP forward
+        let value = read_bytes!(self, f64);
 
-        // Convert 8 bytes to f64 (using Big Endian)
-        let value = f64::from_be_bytes(bytes.try_into().expect("Bytecode ended prematurely"));
+        self.push(Value::Float(value));
+        Ok(())
+    }
 
-        // Move the IP forward by 8 bytes
-        self.ip += 8;
+    pub fn handle_lpush(&mut self) -> Result<(), Fault> {
+        // Convert 8 bytes to i64 (using Big Endian) and move the IP forward
+        let value = read_bytes!(self, i64);
 
-        self.push(Value::Float(value));
+        self.push(Value::Long(value));
+        Ok(())
     }
 
-    pub fn handle_pop(&mut self){
-        self.pop();
+    pub fn handle_pop(&mut self) -> Result<(), Fault> {
+        self.try_pop()?;
+        Ok(())
     }
 
-    pub fn handle_bipush(&mut self){
-        let data = self.fetch() as i32;
+    pub fn handle_bipush(&mut self) -> Result<(), Fault> {
+        let data = read_bytes!(self, u8) as i32;
         self.push(Value::Int(data));
+        Ok(())
     }
-    pub fn handle_swp(&mut self){
-        let a = self.pop();
-        let b = self.pop();
+    pub fn handle_swp(&mut self) -> Result<(), Fault> {
+        let a = self.try_pop()?;
+        let b = self.try_pop()?;
         self.push(a);
         self.push(b);
+        Ok(())
     }
 
-    pub fn handle_dup(&mut self){
-        let a = self.pop();
+    pub fn handle_dup(&mut self) -> Result<(), Fault> {
+        let a = self.try_pop()?;
         self.push(a);
         self.push(a);
+        Ok(())
     }
 
-    pub fn handle_neg(&mut self){
-        let a = self.pop();
+    pub fn handle_neg(&mut self) -> Result<(), Fault> {
+        let a = self.try_pop()?;
 
         let result = match a  {
-            Value::Int(v1) => Value::Int(-v1),
+            Value::Int(v1) => Value::Int(self.int_op("NEG", v1.checked_neg(), v1.wrapping_neg(), v1.saturating_neg())?),
+            Value::Long(v1) => Value::Long(self.int_op("NEG", v1.checked_neg(), v1.wrapping_neg(), v1.saturating_neg())?),
             Value::Float(v1) => Value::Float(-v1),
-            _ => panic!("Type error: Negation only supported for integers and float"),
+            _ => return Err(Fault::Type("Type error: Negation only supported for integers and float".to_string())),
         };
 
         self.push(result);
+        Ok(())
     }
 
-    pub fn handle_add(&mut self){
-        let a = self.pop();
-        let b = self.pop();
+    pub fn handle_add(&mut self) -> Result<(), Fault> {
+        let a = self.try_pop()?;
+        let b = self.try_pop()?;
 
-        let result = match(a,b) {
-            (Value::Int(v1) , Value::Int(v2)) => Value::Int(v1+v2),
+        let result = match Self::promote(a, b) {
+            (Value::Int(v1) , Value::Int(v2)) => {
+                Value::Int(self.int_op("ADD", v1.checked_add(v2), v1.wrapping_add(v2), v1.saturating_add(v2))?)
+            }
+            (Value::Long(v1), Value::Long(v2)) => {
+                Value::Long(self.int_op("ADD", v1.checked_add(v2), v1.wrapping_add(v2), v1.saturating_add(v2))?)
+            }
             (Value::Float(v1) , Value::Float(v2)) => Value::Float(v1+v2),
             (Value::Int(v1), Value::Float(v2)) => Value::Float(v1 as f64 + v2),
             (Value::Float(v1), Value::Int(v2)) => Value::Float(v1 + v2 as f64),
-            _ => panic!("Type error: Addition only supported for integers and float"),
+            _ => return Err(Fault::Type("Type error: Addition only supported for integers and float".to_string())),
         };
 
         self.push(result);
+        Ok(())
     }
 
-    pub fn handle_sub(&mut self){
-        let a = self.pop();
-        let b = self.pop();
+    pub fn handle_sub(&mut self) -> Result<(), Fault> {
+        let a = self.try_pop()?;
+        let b = self.try_pop()?;
 
-        let result = match(a,b) {
-            (Value::Int(v1) , Value::Int(v2)) => Value::Int(v2 - v1),
+        let result = match Self::promote(a, b) {
+            (Value::Int(v1) , Value::Int(v2)) => {
+                Value::Int(self.int_op("SUB", v2.checked_sub(v1), v2.wrapping_sub(v1), v2.saturating_sub(v1))?)
+            }
+            (Value::Long(v1), Value::Long(v2)) => {
+                Value::Long(self.int_op("SUB", v2.checked_sub(v1), v2.wrapping_sub(v1), v2.saturating_sub(v1))?)
+            }
             (Value::Float(v1) , Value::Float(v2)) => Value::Float(v2 - v1),
             (Value::Int(v1), Value::Float(v2)) => Value::Float(v2 - v1 as f64),
             (Value::Float(v1), Value::Int(v2)) => Value::Float(v2 as f64 - v1),
-            _ => panic!("Type error: Subtraction only supported for integers and float"),
+            _ => return Err(Fault::Type("Type error: Subtraction only supported for integers and float".to_string())),
         };
 
         self.push(result);
+        Ok(())
     }
 
-    pub fn handle_mul(&mut self) {
-        let (b, a) = (self.pop(), self.pop());
-        let result = match (a, b) {
-            (Value::Int(v1), Value::Int(v2)) => Value::Int(v1 * v2),
+    pub fn handle_mul(&mut self) -> Result<(), Fault> {
+        let (b, a) = (self.try_pop()?, self.try_pop()?);
+        let result = match Self::promote(a, b) {
+            (Value::Int(v1), Value::Int(v2)) => {
+                Value::Int(self.int_op("MUL", v1.checked_mul(v2), v1.wrapping_mul(v2), v1.saturating_mul(v2))?)
+            }
+            (Value::Long(v1), Value::Long(v2)) => {
+                Value::Long(self.int_op("MUL", v1.checked_mul(v2), v1.wrapping_mul(v2), v1.saturating_mul(v2))?)
+            }
             (Value::Float(v1), Value::Float(v2)) => Value::Float(v1 * v2),
             (Value::Int(v1), Value::Float(v2)) => Value::Float(v1 as f64 * v2),
             (Value::Float(v1), Value::Int(v2)) => Value::Float(v1 * v2 as f64),
-            _ => panic!("Type error: Multiplication only supported for numeric types"),
+            _ => return Err(Fault::Type("Type error: Multiplication only supported for numeric types".to_string())),
         };
         self.push(result);
+        Ok(())
     }
 
-    pub fn handle_div(&mut self) {
-        let b = self.pop();
-        let a = self.pop();
+    pub fn handle_div(&mut self) -> Result<(), Fault> {
+        let b = self.try_pop()?;
+        let a = self.try_pop()?;
 
-        let result = match (a, b) {
+        let result = match Self::promote(a, b) {
             (Value::Int(v1), Value::Int(v2)) => {
-                if v2 == 0 { panic!("Runtime Error: Division by zero"); }
-                Value::Int(v1 / v2)
+                if v2 == 0 { return Err(Fault::DivisionByZero("Runtime Error: Division by zero".to_string())); }
+                // Only MIN / -1 can overflow
+                Value::Int(self.int_op("DIV", v1.checked_div(v2), v1.wrapping_div(v2), v1.saturating_div(v2))?)
+            }
+            (Value::Long(v1), Value::Long(v2)) => {
+                if v2 == 0 { return Err(Fault::DivisionByZero("Runtime Error: Division by zero".to_string())); }
+                Value::Long(self.int_op("DIV", v1.checked_div(v2), v1.wrapping_div(v2), v1.saturating_div(v2))?)
             }
             (Value::Float(v1), Value::Float(v2)) => {
-                if v2 == 0.0 { panic!("Runtime Error: Division by zero"); }
+                if v2 == 0.0 { return Err(Fault::DivisionByZero("Runtime Error: Division by zero".to_string())); }
                 Value::Float(v1 / v2)
             }
             (Value::Int(v1), Value::Float(v2)) => {
-                if v2 == 0.0 { panic!("Runtime Error: Division by zero"); }
+                if v2 == 0.0 { return Err(Fault::DivisionByZero("Runtime Error: Division by zero".to_string())); }
                 Value::Float(v1 as f64 / v2)
             }
             (Value::Float(v1), Value::Int(v2)) => {
-                if v2 == 0 { panic!("Runtime Error: Division by zero"); }
+                if v2 == 0 { return Err(Fault::DivisionByZero("Runtime Error: Division by zero".to_string())); }
                 Value::Float(v1 / v2 as f64)
             }
-            _ => panic!("Type error: Division only supported for numeric types"),
+            _ => return Err(Fault::Type("Type error: Division only supported for numeric types".to_string())),
         };
         self.push(result);
+        Ok(())
     }
 
-    pub fn handle_mod(&mut self) {
-        let b = self.pop();
-        let a = self.pop();
+    pub fn handle_mod(&mut self) -> Result<(), Fault> {
+        let b = self.try_pop()?;
+        let a = self.try_pop()?;
 
-        let result = match (a, b) {
+        let result = match Self::promote(a, b) {
             (Value::Int(v1), Value::Int(v2)) => {
-                if v2 == 0 { panic!("Runtime Error: Integer modulo by zero"); }
-                Value::Int(v1 % v2)
+                if v2 == 0 { return Err(Fault::DivisionByZero("Runtime Error: Integer modulo by zero".to_string())); }
+                // MIN % -1 is 0, but traps like the matching division would
+                Value::Int(self.int_op("MOD", v1.checked_rem(v2), v1.wrapping_rem(v2), v1.wrapping_rem(v2))?)
+            }
+            (Value::Long(v1), Value::Long(v2)) => {
+                if v2 == 0 { return Err(Fault::DivisionByZero("Runtime Error: Integer modulo by zero".to_string())); }
+                Value::Long(self.int_op("MOD", v1.checked_rem(v2), v1.wrapping_rem(v2), v1.wrapping_rem(v2))?)
             }
             (Value::Float(v1), Value::Float(v2)) => {
-                if v2 == 0.0 { panic!("Runtime Error: Float modulo by zero"); }
+                if v2 == 0.0 { return Err(Fault::DivisionByZero("Runtime Error: Float modulo by zero".to_string())); }
                 Value::Float(v1 % v2)
             }
             (Value::Int(v1), Value::Float(v2)) => {
-                if v2 == 0.0 { panic!("Runtime Error: Float modulo by zero"); }
+                if v2 == 0.0 { return Err(Fault::DivisionByZero("Runtime Error: Float modulo by zero".to_string())); }
                 Value::Float(v1 as f64 % v2)
             }
             (Value::Float(v1), Value::Int(v2)) => {
-                if v2 == 0 { panic!("Runtime Error: Integer modulo by zero"); }
+                if v2 == 0 { return Err(Fault::DivisionByZero("Runtime Error: Integer modulo by zero".to_string())); }
                 Value::Float(v1 % v2 as f64)
             }
 
-            _ => panic!("Type error: Modulo only supported for numeric types"),
+            _ => return Err(Fault::Type("Type error: Modulo only supported for numeric types".to_string())),
         };
         self.push(result);
+        Ok(())
     }
 
-    pub fn handle_cmp(&mut self) {
-        let b = self.pop();
-        let a = self.pop();
+    pub fn handle_cmp(&mut self) -> Result<(), Fault> {
+        let b = self.try_pop()?;
+        let a = self.try_pop()?;
 
-        let res = match (a, b) {
+        let res = match Self::promote(a, b) {
             // Integer vs Integer
             (Value::Int(v1), Value::Int(v2)) => {
-                if v1 < v2 { -1 } else if v1 > v2 { 1 } else { 0 }
+                if v1 < v2 { CMP_LESS } else if v1 > v2 { CMP_GREATER } else { CMP_EQUAL }
+            }
+            // Long vs Long, including an Int widened to Long
+            (Value::Long(v1), Value::Long(v2)) => {
+                if v1 < v2 { CMP_LESS } else if v1 > v2 { CMP_GREATER } else { CMP_EQUAL }
             }
             // Float vs Float
             (Value::Float(v1), Value::Float(v2)) => self.compare_f64(v1, v2),
@@ -288,135 +723,717 @@ impl VirtualMachine{
             // Mixed: Float vs Int
             (Value::Float(v1), Value::Int(v2)) => self.compare_f64(v1, v2 as f64),
             
-            _ => panic!("Type error: CMP only supported for numeric types"),
+            _ => return Err(Fault::Type("Type error: CMP only supported for numeric types".to_string())),
         };
 
         self.push(Value::Int(res));
+        Ok(())
     }
 
-    pub fn handle_jl(&mut self) {
-        let address = read_bytes!(self, u32);
+    /// Transfers control to the innermost handler covering `addr`: the stack is
+    /// cut back to the handler's depth and the exception pushed. Fatal faults
+    /// and faults outside every protected range are returned unchanged.
+    fn raise(&mut self, addr: usize, fault: Fault) -> Result<(), Fault> {
+        let exception = match fault.exception() {
+            Some(exception) => exception,
+            None => return Err(fault),
+        };
+        let innermost = self.handlers.iter()
+            .filter(|h| (h.start..h.end).contains(&addr))
+            .min_by_key(|h| h.end - h.start);
+        let handler = match innermost {
+            Some(&handler) => handler,
+            None => return Err(fault),
+        };
+        if self.stack.len() < handler.depth {
+            return Err(Fault::Fatal(format!(
+                "Runtime Error: Handler at {} expects a stack depth of {}, found {}",
+                handler.handler, handler.depth, self.stack.len()
+            )));
+        }
+        while self.stack.len() > handler.depth {
+            self.pop();
+        }
+        self.push(exception);
+        self.ip = handler.handler;
+        Ok(())
+    }
 
-        if let Value::Int(cmp_result) = self.pop() {
-            if cmp_result == -1 {
-                self.ip = address as usize;
-            }
-        } else {
-            panic!("Type error: JL expects an integer on the stack from a CMP operation");
+    /// The error reported for a fault that stopped the machine
+    fn fault_message(&self, fault: Fault) -> String {
+        match fault {
+            Fault::Thrown(value) => format!("Runtime Error: Uncaught exception {}", self.format_value(value, &mut Vec::new())),
+            Fault::Type(message) | Fault::DivisionByZero(message) | Fault::Bounds(message) | Fault::Arithmetic(message) | Fault::Fatal(message) => message,
         }
     }
 
+    /// value -> ; raises the popped value as an exception
+    pub fn handle_throw(&mut self) -> Result<(), Fault> {
+        Err(Fault::Thrown(self.try_pop()?))
+    }
 
-    pub fn handle_jle(&mut self) {
+    /// Pops the operand of a jump and decides whether it is taken. `code` is
+    /// the absolute form of the jump and `name` the mnemonic used in errors.
+    fn jump_taken(&mut self, code: u8, name: &str) -> Result<bool, Fault> {
+        if code == op::JMP {
+            return Ok(true);
+        }
+        match (code, self.try_pop()?) {
+            (op::JT, Value::Bool(b)) => Ok(b),
+            (op::JF, Value::Bool(b)) => Ok(!b),
+            (op::JT | op::JF, _) => Err(Fault::Type(format!("Type error: {} expects a boolean on the stack", name))),
+            (_, Value::Int(cmp_result)) => Ok(match code {
+                op::JL => cmp_result == CMP_LESS,
+                op::JLE => cmp_result == CMP_LESS || cmp_result == CMP_EQUAL,
+                op::JG => cmp_result == CMP_GREATER,
+                op::JGE => cmp_result == CMP_GREATER || cmp_result == CMP_EQUAL,
+                op::JE => cmp_result == CMP_EQUAL,
+                op::JNE => cmp_result != CMP_EQUAL,
+                _ => cmp_result == CMP_UNORDERED, // JU
+            }),
+            _ => Err(Fault::Type(format!("Type error: {} expects an integer on the stack from a CMP operation", name))),
+        }
+    }
+
+    /// Jumps with a 4-byte absolute address (JMP, JL, JT, ...)
+    pub fn handle_jump(&mut self, code: u8) -> Result<(), Fault> {
         let address = read_bytes!(self, u32);
 
-        if let Value::Int(cmp_result) = self.pop() {
-            if cmp_result == -1 || cmp_result == 0 {
-                self.ip = address as usize;
-            }
-        } else {
-            panic!("Type error: JLE expects an integer on the stack from a CMP operation");
+        if self.jump_taken(code, op::get_info(code).unwrap().name)? {
+            self.ip = address as usize;
         }
+        Ok(())
     }
 
-    pub fn handle_jg(&mut self) {
-        let address = read_bytes!(self, u32);
+    /// Jumps with a signed displacement from the address of the jump itself (JMP8, JL16, ...)
+    pub fn handle_relative_jump(&mut self, code: u8) -> Result<(), Fault> {
+        let addr = self.ip - 1;
+        let (absolute, width) = relative_jump_info(code).unwrap();
+        let offset = match width {
+            1 => read_bytes!(self, i8) as i64,
+            2 => read_bytes!(self, i16) as i64,
+            _ => read_bytes!(self, i32) as i64,
+        };
 
-        if let Value::Int(cmp_result) = self.pop() {
-            if cmp_result == 1 {
-                self.ip = address as usize;
+        let name = op::get_info(code).unwrap().name;
+        if self.jump_taken(absolute, name)? {
+            let target = addr as i64 + offset;
+            if target < 0 {
+                return Err(Fault::Fatal(format!("Runtime Error: Invalid jump target {} in {}", target, name)));
             }
-        } else {
-            panic!("Type error: JG expects an integer on the stack from a CMP operation");
+            self.ip = target as usize;
         }
+        Ok(())
     }
 
+    pub fn handle_isnan(&mut self) -> Result<(), Fault> {
+        let result = match self.try_pop()? {
+            Value::Float(v) => v.is_nan(),
+            Value::Int(_) | Value::Long(_) => false,
+            _ => return Err(Fault::Type("Type error: ISNAN only supported for numeric types".to_string())),
+        };
+        self.push(Value::Bool(result));
+        Ok(())
+    }
 
-    pub fn handle_jge(&mut self) {
-        let address = read_bytes!(self, u32);
+    /// address -> ; jumps to a computed address, e.g. one pushed by ADDR
+    pub fn handle_jmpi(&mut self) -> Result<(), Fault> {
+        match self.try_pop()? {
+            Value::Int(address) if address >= 0 => self.ip = address as usize,
+            Value::Int(address) => return Err(Fault::Fatal(format!("Runtime Error: Invalid jump target {} in JMPI", address))),
+            _ => return Err(Fault::Type("Type error: JMPI expects an integer address".to_string())),
+        }
+        Ok(())
+    }
 
-        if let Value::Int(cmp_result) = self.pop() {
-            if cmp_result == 1 || cmp_result == 0 {
-                self.ip = address as usize;
-            }
+    /// selector -> ; jumps to case `selector - low` of the table, or to the
+    /// default target when the selector is out of range
+    pub fn handle_tableswitch(&mut self) -> Result<(), Fault> {
+        let low = read_bytes!(self, i32);
+        let default = read_bytes!(self, u32);
+        let count = read_bytes!(self, u32);
+
+        let selector = match self.try_pop()? {
+            Value::Int(v) => v,
+            _ => return Err(Fault::Type("Type error: TABLESWITCH expects an integer selector".to_string())),
+        };
+        let index = selector as i64 - low as i64;
+        if (0..count as i64).contains(&index) {
+            self.ip += 4 * index as usize;
+            self.ip = read_bytes!(self, u32) as usize;
         } else {
-            panic!("Type error: JGE expects an integer on the stack from a CMP operation");
+            self.ip = default as usize;
         }
+        Ok(())
     }
 
-    pub fn handle_je(&mut self) {
-        let address = read_bytes!(self, u32);
+    pub fn handle_store(&mut self) -> Result<(), Fault> {
+        let address = read_bytes!(self, u32) as usize;
 
-        if let Value::Int(cmp_result) = self.pop() {
-            if cmp_result == 0  {
-                self.ip = address as usize;
-            }
+        if let Ok(value) = self.try_pop() {
+            self.write_memory(address, value);
         } else {
-            panic!("Type error: JE expects an integer on the stack from a CMP operation");
+            return Err(Fault::Fatal("Runtime Error: Stack underflow during STORE".to_string()));
         }
+        Ok(())
     }
 
-    pub fn handle_jne(&mut self) {
-        let address = read_bytes!(self, u32);
 
-        if let Value::Int(cmp_result) = self.pop() {
-            if cmp_result != 0  {
-                self.ip = address as usize;
-            }
+    pub fn handle_load(&mut self) -> Result<(), Fault> {
+        let address = read_bytes!(self, u32) as usize;
+        self.load_from(address)
+    }
+
+    /// Pushes `memory[address]`, shared by LOAD and LOADI
+    fn load_from(&mut self, address: usize) -> Result<(), Fault> {
+        if address < self.memory.len() {
+            let value = self.memory[address];
+            self.push(value);
         } else {
-            panic!("Type error: JNE expects an integer on the stack from a CMP operation");
+            return Err(Fault::Bounds(format!("Runtime Error: Access to uninitialized or out-of-bounds address: {}", address)));
         }
+        Ok(())
     }
 
-    pub fn handle_jmp(&mut self) {
+    /// Reads a memory address popped by LOADI or STOREI
+    fn address_operand(name: &str, value: Value) -> Result<usize, Fault> {
+        let address = match value {
+            Value::Int(a) => a as i64,
+            Value::Long(a) => a,
+            _ => return Err(Fault::Type(format!("Type error: {} expects an integer address", name))),
+        };
+        usize::try_from(address).map_err(|_| Fault::Bounds(format!("Runtime Error: Invalid address {} in {}", address, name)))
+    }
+
+    pub fn handle_loadi(&mut self) -> Result<(), Fault> {
+        let address = Self::address_operand("LOADI", self.try_pop()?)?;
+        self.load_from(address)
+    }
+
+    /// address, value -> ; grows memory like STORE
+    pub fn handle_storei(&mut self) -> Result<(), Fault> {
+        let value = self.try_pop()?;
+        let address = Self::address_operand("STOREI", self.try_pop()?)?;
+        self.write_memory(address, value);
+        Ok(())
+    }
+
+    pub fn handle_addr(&mut self) -> Result<(), Fault> {
         let address = read_bytes!(self, u32);
-        self.ip = address as usize;
+        self.push(Value::Int(address as i32));
+        Ok(())
     }
 
-    pub fn handle_store(&mut self) {
-        let address = read_bytes!(self, u32) as usize;
+    pub fn handle_print(&mut self) -> Result<(), Fault> {
+        let item = self.try_pop()?;
+        println!("{}", self.format_value(item, &mut Vec::new()));
+        Ok(())
+    }
 
-        if let Some(value) = self.stack.pop() {
-            if address >= self.memory.len() {
-                self.memory.resize(address + 1, Value::Int(0));
+    /// Formats a value the way PRINT shows it. Arrays and records print their
+    /// contents; `path` holds the objects being printed so a cycle prints as
+    /// `[...]` or `Name {...}`.
+    pub fn format_value(&self, value: Value, path: &mut Vec<usize>) -> String {
+        match value {
+            Value::Int(val) => val.to_string(),
+            Value::Long(val) => val.to_string(),
+            Value::Float(val) => format!("{:.2}", val),
+            Value::Char(c) => (c as char).to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::Ref(r) => {
+                let object = match self.heap.get(r) {
+                    Some(object) => object,
+                    None => return format!("<invalid ref {}>", r),
+                };
+                let name = match object {
+                    Object::Array(_) => None,
+                    Object::Record { layout, .. } => Some(match self.structs.get(*layout) {
+                        Some(s) => s.name.clone(),
+                        None => format!("<struct {}>", layout),
+                    }),
+                };
+                if path.contains(&r) {
+                    return match name {
+                        Some(name) => format!("{} {{...}}", name),
+                        None => "[...]".to_string(),
+                    };
+                }
+
+                path.push(r);
+                let items: Vec<String> = object.values().iter().map(|&v| self.format_value(v, path)).collect();
+                path.pop();
+                match (object, name) {
+                    (Object::Record { layout, .. }, Some(name)) => {
+                        let fields = self.structs.get(*layout).map(|s| &s.fields[..]).unwrap_or(&[]);
+                        let items: Vec<String> = items.iter().enumerate().map(|(i, item)| match fields.get(i) {
+                            Some(field) => format!("{}: {}", field, item),
+                            None => item.clone(),
+                        }).collect();
+                        if items.is_empty() {
+                            format!("{} {{}}", name)
+                        } else {
+                            format!("{} {{ {} }}", name, items.join(", "))
+                        }
+                    }
+                    _ => format!("[{}]", items.join(", ")),
+                }
             }
-            self.memory[address] = value;
-        } else {
-            panic!("Runtime Error: Stack underflow during STORE");
         }
     }
 
+    pub fn handle_and(&mut self) -> Result<(), Fault> {
+        let b = self.try_pop()?;
+        let a = self.try_pop()?;
 
-    pub fn handle_load(&mut self) {
-        let address = read_bytes!(self, u32) as usize;
-        if address < self.memory.len() {
-            let value = self.memory[address];
-            self.stack.push(value);
-        } else {
-            eprintln!("Runtime Error: Access to uninitialized or out-of-bounds address: {}", address);
-            self.running = false;
+        let result = match Self::promote(a, b) {
+            (Value::Int(v1), Value::Int(v2)) => Value::Int(v1 & v2),
+            (Value::Long(v1), Value::Long(v2)) => Value::Long(v1 & v2),
+            (Value::Bool(v1), Value::Bool(v2)) => Value::Bool(v1 && v2),
+            _ => return Err(Fault::Type("Type error: AND only supported for integers and booleans".to_string())),
+        };
+        self.push(result);
+        Ok(())
+    }
+
+    pub fn handle_or(&mut self) -> Result<(), Fault> {
+        let b = self.try_pop()?;
+        let a = self.try_pop()?;
+
+        let result = match Self::promote(a, b) {
+            (Value::Int(v1), Value::Int(v2)) => Value::Int(v1 | v2),
+            (Value::Long(v1), Value::Long(v2)) => Value::Long(v1 | v2),
+            (Value::Bool(v1), Value::Bool(v2)) => Value::Bool(v1 || v2),
+            _ => return Err(Fault::Type("Type error: OR only supported for integers and booleans".to_string())),
+        };
+        self.push(result);
+        Ok(())
+    }
+
+    pub fn handle_xor(&mut self) -> Result<(), Fault> {
+        let b = self.try_pop()?;
+        let a = self.try_pop()?;
+
+        let result = match Self::promote(a, b) {
+            (Value::Int(v1), Value::Int(v2)) => Value::Int(v1 ^ v2),
+            (Value::Long(v1), Value::Long(v2)) => Value::Long(v1 ^ v2),
+            (Value::Bool(v1), Value::Bool(v2)) => Value::Bool(v1 ^ v2),
+            _ => return Err(Fault::Type("Type error: XOR only supported for integers and booleans".to_string())),
+        };
+        self.push(result);
+        Ok(())
+    }
+
+    pub fn handle_not(&mut self) -> Result<(), Fault> {
+        let result = match self.try_pop()? {
+            Value::Int(v1) => Value::Int(!v1),
+            Value::Long(v1) => Value::Long(!v1),
+            Value::Bool(v1) => Value::Bool(!v1),
+            _ => return Err(Fault::Type("Type error: NOT only supported for integers and booleans".to_string())),
+        };
+        self.push(result);
+        Ok(())
+    }
+
+    /// Reads a shift count; it is reduced modulo the width of the shifted value
+    fn shift_count(name: &str, count: Value) -> Result<u32, Fault> {
+        match count {
+            Value::Int(n) => Ok(n as u32),
+            Value::Long(n) => Ok(n as u32),
+            _ => Err(Fault::Type(format!("Type error: {} only supported for integers", name))),
         }
     }
 
-    pub fn handle_print(&mut self) {
-        let item = self.pop();
-        
-        match item {
-            Value::Int(val) => {
-                println!("{}", val);
-            }
-            Value::Float(val) => {
-                println!("{:.2}", val); 
+    pub fn handle_shl(&mut self) -> Result<(), Fault> {
+        let b = self.try_pop()?;
+        let a = self.try_pop()?;
+
+        let n = Self::shift_count("SHL", b)?;
+        let result = match a {
+            Value::Int(v1) => Value::Int(v1.wrapping_shl(n)),
+            Value::Long(v1) => Value::Long(v1.wrapping_shl(n)),
+            _ => return Err(Fault::Type("Type error: SHL only supported for integers".to_string())),
+        };
+        self.push(result);
+        Ok(())
+    }
+
+    pub fn handle_shr(&mut self) -> Result<(), Fault> {
+        let b = self.try_pop()?;
+        let a = self.try_pop()?;
+
+        let n = Self::shift_count("SHR", b)?;
+        let result = match a {
+            Value::Int(v1) => Value::Int(v1.wrapping_shr(n)),
+            Value::Long(v1) => Value::Long(v1.wrapping_shr(n)),
+            _ => return Err(Fault::Type("Type error: SHR only supported for integers".to_string())),
+        };
+        self.push(result);
+        Ok(())
+    }
+
+    pub fn handle_ushr(&mut self) -> Result<(), Fault> {
+        let b = self.try_pop()?;
+        let a = self.try_pop()?;
+
+        let n = Self::shift_count("USHR", b)?;
+        let result = match a {
+            Value::Int(v1) => Value::Int((v1 as u32).wrapping_shr(n) as i32),
+            Value::Long(v1) => Value::Long((v1 as u64).wrapping_shr(n) as i64),
+            _ => return Err(Fault::Type("Type error: USHR only supported for integers".to_string())),
+        };
+        self.push(result);
+        Ok(())
+    }
+
+    pub fn handle_i2l(&mut self) -> Result<(), Fault> {
+        match self.try_pop()? {
+            Value::Int(v) => self.push(Value::Long(v as i64)),
+            _ => return Err(Fault::Type("Type error: I2L expects an integer".to_string())),
+        }
+        Ok(())
+    }
+
+    /// Narrows a Long to an Int; values outside the i32 range follow the overflow mode
+    pub fn handle_l2i(&mut self) -> Result<(), Fault> {
+        let result = match self.try_pop()? {
+            Value::Long(v) => {
+                let saturated = v.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+                self.int_op("L2I", i32::try_from(v).ok(), v as i32, saturated)?
             }
-            Value::Char(c) => {
-                println!("{}", c as char);
+            _ => return Err(Fault::Type("Type error: L2I expects a long".to_string())),
+        };
+        self.push(Value::Int(result));
+        Ok(())
+    }
+
+    /// Converts a Long to the nearest Float; magnitudes above 2^53 may lose precision
+    pub fn handle_l2f(&mut self) -> Result<(), Fault> {
+        match self.try_pop()? {
+            Value::Long(v) => self.push(Value::Float(v as f64)),
+            _ => return Err(Fault::Type("Type error: L2F expects a long".to_string())),
+        }
+        Ok(())
+    }
+
+    pub fn handle_i2f(&mut self) -> Result<(), Fault> {
+        match self.try_pop()? {
+            Value::Int(v) => self.push(Value::Float(v as f64)),
+            _ => return Err(Fault::Type("Type error: I2F expects an integer".to_string())),
+        }
+        Ok(())
+    }
+
+    pub fn handle_f2i(&mut self) -> Result<(), Fault> {
+        let mode = read_bytes!(self, u8);
+        let result = match self.try_pop()? {
+            Value::Float(v) => self.float_to_int("F2I", v, mode, 2f64.powi(31), |r| r as i32)?,
+            _ => return Err(Fault::Type("Type error: F2I expects a float".to_string())),
+        };
+        self.push(Value::Int(result));
+        Ok(())
+    }
+
+    pub fn handle_f2l(&mut self) -> Result<(), Fault> {
+        let mode = read_bytes!(self, u8);
+        let result = match self.try_pop()? {
+            Value::Float(v) => self.float_to_int("F2L", v, mode, 2f64.powi(63), |r| r as i64)?,
+            _ => return Err(Fault::Type("Type error: F2L expects a float".to_string())),
+        };
+        self.push(Value::Long(result));
+        Ok(())
+    }
+
+    /// Reads a numeric operand of a math instruction as a Float
+    fn math_operand(name: &str, value: Value) -> Result<f64, Fault> {
+        match value {
+            Value::Int(v) => Ok(v as f64),
+            Value::Long(v) => Ok(v as f64),
+            Value::Float(v) => Ok(v),
+            _ => Err(Fault::Type(format!("Type error: {} only supported for numeric types", name))),
+        }
+    }
+
+    /// Pops one number, applies `f` and pushes the Float result
+    fn float_unary(&mut self, name: &str, f: fn(f64) -> f64) -> Result<(), Fault> {
+        let v = Self::math_operand(name, self.try_pop()?)?;
+        self.push(Value::Float(f(v)));
+        Ok(())
+    }
+
+    pub fn handle_pow(&mut self) -> Result<(), Fault> {
+        let exponent = Self::math_operand("POW", self.try_pop()?)?;
+        let base = Self::math_operand("POW", self.try_pop()?)?;
+        self.push(Value::Float(base.powf(exponent)));
+        Ok(())
+    }
+
+    pub fn handle_abs(&mut self) -> Result<(), Fault> {
+        let result = match self.try_pop()? {
+            Value::Int(v) => Value::Int(self.int_op("ABS", v.checked_abs(), v.wrapping_abs(), v.saturating_abs())?),
+            Value::Long(v) => Value::Long(self.int_op("ABS", v.checked_abs(), v.wrapping_abs(), v.saturating_abs())?),
+            Value::Float(v) => Value::Float(v.abs()),
+            _ => return Err(Fault::Type("Type error: ABS only supported for numeric types".to_string())),
+        };
+        self.push(result);
+        Ok(())
+    }
+
+    /// FLOOR and CEIL: Floats are rounded, integers are already whole and stay as they are
+    fn handle_round_float(&mut self, name: &str, f: fn(f64) -> f64) -> Result<(), Fault> {
+        let result = match self.try_pop()? {
+            Value::Float(v) => Value::Float(f(v)),
+            v @ (Value::Int(_) | Value::Long(_)) => v,
+            _ => return Err(Fault::Type(format!("Type error: {} only supported for numeric types", name))),
+        };
+        self.push(result);
+        Ok(())
+    }
+
+    /// MIN and MAX. Float results follow `f64::min`/`f64::max`, which return
+    /// the other operand when one of them is NaN.
+    fn handle_min_max(&mut self, name: &str, max: bool) -> Result<(), Fault> {
+        let b = self.try_pop()?;
+        let a = self.try_pop()?;
+
+        let result = match Self::promote(a, b) {
+            (Value::Int(v1), Value::Int(v2)) => Value::Int(if max { v1.max(v2) } else { v1.min(v2) }),
+            (Value::Long(v1), Value::Long(v2)) => Value::Long(if max { v1.max(v2) } else { v1.min(v2) }),
+            (a, b) => {
+                let (v1, v2) = (Self::math_operand(name, a)?, Self::math_operand(name, b)?);
+                Value::Float(if max { v1.max(v2) } else { v1.min(v2) })
             }
+        };
+        self.push(result);
+        Ok(())
+    }
+
+    /// Orders two values for the boolean comparisons. Numbers are promoted
+    /// like in ADD and chars compare by code; booleans only support EQ and NE.
+    /// Returns None when the values are unordered, i.e. one of them is NaN.
+    fn order_values(name: &str, a: Value, b: Value) -> Result<Option<Ordering>, Fault> {
+        let order = match Self::promote(a, b) {
+            (Value::Int(v1), Value::Int(v2)) => Some(v1.cmp(&v2)),
+            (Value::Long(v1), Value::Long(v2)) => Some(v1.cmp(&v2)),
+            (Value::Float(v1), Value::Float(v2)) => v1.partial_cmp(&v2),
+            (Value::Int(v1), Value::Float(v2)) => (v1 as f64).partial_cmp(&v2),
+            (Value::Float(v1), Value::Int(v2)) => v1.partial_cmp(&(v2 as f64)),
+            (Value::Char(v1), Value::Char(v2)) => Some(v1.cmp(&v2)),
+            (Value::Bool(v1), Value::Bool(v2)) if name == "EQ" || name == "NE" => Some(v1.cmp(&v2)),
+            _ => return Err(Fault::Type(format!("Type error: {} cannot compare {:?} and {:?}", name, a, b))),
+        };
+        Ok(order)
+    }
+
+    /// Pops two values and pushes whether `test` accepts their ordering
+    fn handle_compare(&mut self, name: &str, test: fn(Option<Ordering>) -> bool) -> Result<(), Fault> {
+        let b = self.try_pop()?;
+        let a = self.try_pop()?;
+
+        let order = Self::order_values(name, a, b)?;
+        self.push(Value::Bool(test(order)));
+        Ok(())
+    }
+
+    /// Puts the object made by `build` on the heap, collecting garbage first
+    /// when the heap has grown past its threshold or the allocation would
+    /// exceed `heap_limit`. `slots` must match `Object::slots` of the result;
+    /// it is checked before `build` runs so an oversized object is never made.
+    fn allocate(&mut self, slots: usize, build: impl FnOnce(&Self) -> Object) -> Result<usize, Fault> {
+        let over_limit = |vm: &Self| vm.config.heap_limit.is_some_and(|limit| vm.heap.size() + slots > limit);
+
+        if self.heap.wants_collection(slots) || over_limit(self) {
+            self.collect_garbage();
+        }
+        if over_limit(self) {
+            return Err(Fault::Fatal(format!(
+                "Runtime Error: Heap limit of {} slots exceeded ({} in use, {} requested)",
+                self.config.heap_limit.unwrap(), self.heap.size(), slots
+            )));
+        }
+
+        let object = build(self);
+        let reference = self.heap.alloc(object);
+        self.journal(Mutation::Alloc(reference));
+        Ok(reference)
+    }
+
+    /// Runs a full mark-and-sweep collection rooted in the stack, memory,
+    /// constants and recorded history. Returns the number of objects freed.
+    pub fn collect_garbage(&mut self) -> usize {
+        let saved = self.history.iter().flat_map(|h| h.saved_values());
+        let roots = self.stack.iter().chain(&self.memory).chain(&self.constants).chain(saved);
+        self.heap.collect(roots)
+    }
+
+    /// Resolves an array reference popped by an array instruction
+    fn array(&self, name: &str, value: Value) -> Result<(usize, &Vec<Value>), Fault> {
+        match value {
+            Value::Ref(r) => match self.heap.get(r) {
+                Some(Object::Array(values)) => Ok((r, values)),
+                Some(_) => Err(Fault::Type(format!("Type error: {} expects an array reference", name))),
+                None => Err(Fault::Fatal(format!("Runtime Error: Invalid reference {} in {}", r, name))),
+            },
+            _ => Err(Fault::Type(format!("Type error: {} expects an array reference", name))),
         }
     }
 
+    /// Overwrites element or field `index` of a heap object, journaling the old value
+    fn write_object(&mut self, reference: usize, index: usize, value: Value) {
+        if let Some(object) = self.heap.get_mut(reference) {
+            let old = std::mem::replace(&mut object.values_mut()[index], value);
+            self.journal(Mutation::ObjectWrite { reference, index, old });
+        }
+    }
+
+    /// Checks an index operand against the length of the array it indexes
+    fn array_index(name: &str, index: Value, len: usize) -> Result<usize, Fault> {
+        let index = match index {
+            Value::Int(i) => i as i64,
+            Value::Long(i) => i,
+            _ => return Err(Fault::Type(format!("Type error: {} expects an integer index", name))),
+        };
+        usize::try_from(index).ok().filter(|&i| i < len).ok_or_else(|| {
+            Fault::Bounds(format!("Runtime Error: Array index {} out of bounds for length {}", index, len))
+        })
+    }
+
+    /// Pops a length and pushes a reference to a new array of that many Int(0)
+    pub fn handle_newarray(&mut self) -> Result<(), Fault> {
+        let len = match self.try_pop()? {
+            Value::Int(n) => n as i64,
+            Value::Long(n) => n,
+            _ => return Err(Fault::Type("Type error: NEWARRAY expects an integer length".to_string())),
+        };
+        if len < 0 {
+            return Err(Fault::Bounds(format!("Runtime Error: Negative array length {}", len)));
+        }
+        // ALEN reports an Int, so no array may be longer than i32::MAX
+        let len = usize::try_from(len).ok().filter(|&n| n <= i32::MAX as usize)
+            .ok_or_else(|| Fault::Bounds(format!("Runtime Error: Array length {} exceeds the maximum of {}", len, i32::MAX)))?;
+
+        let reference = self.allocate(len + 1, |_| Object::Array(vec![Value::Int(0); len]))?;
+        self.push(Value::Ref(reference));
+        Ok(())
+    }
+
+    /// Pops a count n and the n values below it, and pushes a reference to
+    /// a new array holding them in push order
+    pub fn handle_alloc(&mut self) -> Result<(), Fault> {
+        let count = match self.try_pop()? {
+            Value::Int(n) => n as i64,
+            Value::Long(n) => n,
+            _ => return Err(Fault::Type("Type error: ALLOC expects an integer count".to_string())),
+        };
+        let start = usize::try_from(count).ok().and_then(|n| self.stack.len().checked_sub(n))
+            .ok_or_else(|| Fault::Fatal(format!("Runtime Error: ALLOC of {} values with {} on the stack", count, self.stack.len())))?;
+
+        // The values stay on the stack, and so stay rooted, until the allocation succeeded
+        let reference = self.allocate(self.stack.len() - start + 1, |vm| Object::Array(vm.stack[start..].to_vec()))?;
+        for _ in 0..count {
+            self.try_pop()?;
+        }
+        self.push(Value::Ref(reference));
+        Ok(())
+    }
+
+    /// arrayref, index -> value
+    pub fn handle_aload(&mut self) -> Result<(), Fault> {
+        let index = self.try_pop()?;
+        let array = self.try_pop()?;
+
+        let (_, values) = self.array("ALOAD", array)?;
+        let value = values[Self::array_index("ALOAD", index, values.len())?];
+        self.push(value);
+        Ok(())
+    }
+
+    /// arrayref, index, value ->
+    pub fn handle_astore(&mut self) -> Result<(), Fault> {
+        let value = self.try_pop()?;
+        let index = self.try_pop()?;
+        let array = self.try_pop()?;
+
+        let (reference, values) = self.array("ASTORE", array)?;
+        let index = Self::array_index("ASTORE", index, values.len())?;
+        self.write_object(reference, index, value);
+        Ok(())
+    }
+
+    /// arrayref -> length
+    pub fn handle_alen(&mut self) -> Result<(), Fault> {
+        let array = self.try_pop()?;
+        let (_, values) = self.array("ALEN", array)?;
+        let len = values.len() as i32;
+        self.push(Value::Int(len));
+        Ok(())
+    }
+
+    /// Resolves a record reference and field offset for GETFIELD and SETFIELD
+    fn record_field(&self, name: &str, value: Value, field: usize) -> Result<usize, Fault> {
+        let reference = match value {
+            Value::Ref(r) => r,
+            _ => return Err(Fault::Type(format!("Type error: {} expects a record reference", name))),
+        };
+        match self.heap.get(reference) {
+            Some(Object::Record { layout, fields }) if field >= fields.len() => Err(Fault::Bounds(format!(
+                "Runtime Error: Field {} out of bounds for {}",
+                field,
+                self.structs.get(*layout).map_or_else(|| format!("struct {}", layout), |s| s.name.clone())
+            ))),
+            Some(Object::Record { .. }) => Ok(reference),
+            Some(_) => Err(Fault::Type(format!("Type error: {} expects a record reference", name))),
+            None => Err(Fault::Fatal(format!("Runtime Error: Invalid reference {} in {}", reference, name))),
+        }
+    }
+
+    /// Pushes a reference to a new record of the struct given by the operand,
+    /// with every field set to Int(0)
+    pub fn handle_new(&mut self) -> Result<(), Fault> {
+        let layout = read_bytes!(self, u32) as usize;
+        let count = self.structs.get(layout)
+            .ok_or_else(|| Fault::Fatal(format!("Runtime Error: Unknown struct {}", layout)))?
+            .fields.len();
+
+        let reference = self.allocate(count + 1, |_| Object::Record { layout, fields: vec![Value::Int(0); count] })?;
+        self.push(Value::Ref(reference));
+        Ok(())
+    }
+
+    /// recordref -> value, the operand is the field offset
+    pub fn handle_getfield(&mut self) -> Result<(), Fault> {
+        let field = read_bytes!(self, u32) as usize;
+        let record = self.try_pop()?;
+
+        let reference = self.record_field("GETFIELD", record, field)?;
+        let value = self.heap.get(reference).unwrap().values()[field];
+        self.push(value);
+        Ok(())
+    }
+
+    /// recordref, value -> ; the operand is the field offset
+    pub fn handle_setfield(&mut self) -> Result<(), Fault> {
+        let field = read_bytes!(self, u32) as usize;
+        let value = self.try_pop()?;
+        let record = self.try_pop()?;
+
+        let reference = self.record_field("SETFIELD", record, field)?;
+        self.write_object(reference, field, value);
+        Ok(())
+    }
 }
 
 
+/// Number of operands popped by the arithmetic and math instructions that
+/// `trap_nan` checks, or `None` for every other instruction
+fn nan_arity(code: u8) -> Option<usize> {
+    match code {
+        op::NEG | op::SQRT | op::EXP | op::LOG | op::SIN | op::COS | op::TAN
+        | op::ABS | op::FLOOR | op::CEIL => Some(1),
+        op::ADD | op::SUB | op::MUL | op::DIV | op::MOD | op::POW | op::MIN | op::MAX => Some(2),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -477,4 +1494,44 @@ mod tests {
         
         vm.pop();
     }
+
+    #[test]
+    fn test_run_reports_fault_instead_of_panicking() {
+        let mut vm = VirtualMachine::new(vec![op::BIPUSH, 1, op::BIPUSH, 0, op::DIV, op::HALT]);
+
+        let result = vm.run();
+        assert_eq!(result, Err("Runtime Error: Division by zero".to_string()));
+        assert_eq!(vm.fault, result.err());
+        assert!(!vm.running);
+        assert_eq!(vm.ip, 5);
+    }
+
+    #[test]
+    fn test_run_reports_stack_underflow() {
+        let mut vm = VirtualMachine::new(vec![op::ADD]);
+        assert_eq!(vm.run(), Err("Stack underflow!".to_string()));
+    }
+
+    #[test]
+    fn test_run_reports_truncated_operand() {
+        let mut vm = VirtualMachine::new(vec![op::IPUSH, 0, 0]);
+        assert_eq!(vm.run(), Err("Runtime Error: Bytecode ended prematurely".to_string()));
+    }
+
+    #[test]
+    fn test_runtime_error_names_source_line() {
+        let mut assembler = crate::vm::assembler::Assembler::new();
+        let code = assembler.assemble("BIPUSH 1\nloop:\nBIPUSH 0\nDIV\nHALT").unwrap();
+        let mut vm = VirtualMachine::new(code);
+        vm.debug_info = Some(assembler.debug_info("prog.flint"));
+
+        assert_eq!(vm.run(), Err("Runtime Error: Division by zero at prog.flint:4 (in loop)".to_string()));
+    }
+
+    #[test]
+    fn test_load_out_of_bounds_is_a_fault() {
+        let mut vm = VirtualMachine::new(vec![op::LOAD, 0, 0, 0, 3]);
+        let err = vm.run().unwrap_err();
+        assert!(err.contains("out-of-bounds address: 3"));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,533 @@
+use crate::vm::heap::{Heap, Object, StructLayout};
+use crate::vm::runner::{ExceptionHandler, OverflowMode, Value, VirtualMachine, VmConfig};
+
+// Layout (all integers big endian):
+//   magic "FLNTSNAP", u16 version, u64 code hash, u32 code length,
+//   u64 ip, u8 running, the fault (u8 present flag, then a u32 length and
+//   UTF-8 message when present), the config (u8 overflow mode, u8 trap_nan,
+//   u8 heap limit flag and a u64 limit when present), then the stack, memory
+//   and constants sections, each a u32 count followed by tagged values, then
+//   the heap: a u32 slot count followed by a kind byte per slot, plus a value
+//   section for every slot that holds an object (preceded by a u32 layout for
+//   records), and the u64 GC threshold. Last come the struct layouts (u32
+//   count, then a name and a u32 count of field names each, strings being a
+//   u32 length and UTF-8) and the handler table (u32 count, then u64 start,
+//   end, handler and depth each).
+const MAGIC: &[u8; 8] = b"FLNTSNAP";
+pub const VERSION: u16 = 4;
+
+const TAG_INT: u8 = 0;
+const TAG_FLOAT: u8 = 1;
+const TAG_CHAR: u8 = 2;
+const TAG_LONG: u8 = 3;
+const TAG_BOOL: u8 = 4;
+const TAG_REF: u8 = 5;
+
+const KIND_ARRAY: u8 = 0;
+const KIND_RECORD: u8 = 1;
+const KIND_FREE: u8 = 255;
+
+/// 64-bit FNV-1a, used to tie a snapshot to the program it was taken from.
+pub fn code_hash(code: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in code {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn write_string(out: &mut Vec<u8>, text: &str) {
+    out.extend(&(text.len() as u32).to_be_bytes());
+    out.extend(text.as_bytes());
+}
+
+fn write_values(out: &mut Vec<u8>, values: &[Value]) {
+    out.extend(&(values.len() as u32).to_be_bytes());
+    for value in values {
+        match *value {
+            Value::Int(v) => {
+                out.push(TAG_INT);
+                out.extend(&v.to_be_bytes());
+            }
+            Value::Long(v) => {
+                out.push(TAG_LONG);
+                out.extend(&v.to_be_bytes());
+            }
+            Value::Float(v) => {
+                out.push(TAG_FLOAT);
+                out.extend(&v.to_be_bytes());
+            }
+            Value::Char(c) => {
+                out.push(TAG_CHAR);
+                out.push(c);
+            }
+            Value::Bool(b) => {
+                out.push(TAG_BOOL);
+                out.push(b as u8);
+            }
+            Value::Ref(r) => {
+                out.push(TAG_REF);
+                out.extend(&(r as u64).to_be_bytes());
+            }
+        }
+    }
+}
+
+/// Serializes the state of a (usually paused) machine.
+pub fn save(vm: &VirtualMachine) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend(MAGIC);
+    out.extend(&VERSION.to_be_bytes());
+    out.extend(&code_hash(&vm.code).to_be_bytes());
+    out.extend(&(vm.code.len() as u32).to_be_bytes());
+    out.extend(&(vm.ip as u64).to_be_bytes());
+    out.push(vm.running as u8);
+    match &vm.fault {
+        Some(message) => {
+            out.push(1);
+            write_string(&mut out, message);
+        }
+        None => out.push(0),
+    }
+    out.push(match vm.config.overflow {
+        OverflowMode::Wrapping => 0,
+        OverflowMode::Saturating => 1,
+        OverflowMode::Trapping => 2,
+    });
+    out.push(vm.config.trap_nan as u8);
+    match vm.config.heap_limit {
+        Some(limit) => {
+            out.push(1);
+            out.extend(&(limit as u64).to_be_bytes());
+        }
+        None => out.push(0),
+    }
+    write_values(&mut out, &vm.stack);
+    write_values(&mut out, &vm.memory);
+    write_values(&mut out, &vm.constants);
+
+    let slots: Vec<Option<&Object>> = vm.heap.slots().collect();
+    out.extend(&(slots.len() as u32).to_be_bytes());
+    for slot in slots {
+        match slot {
+            Some(Object::Array(values)) => {
+                out.push(KIND_ARRAY);
+                write_values(&mut out, values);
+            }
+            Some(Object::Record { layout, fields }) => {
+                out.push(KIND_RECORD);
+                out.extend(&(*layout as u32).to_be_bytes());
+                write_values(&mut out, fields);
+            }
+            None => out.push(KIND_FREE),
+        }
+    }
+    out.extend(&(vm.heap.gc_threshold() as u64).to_be_bytes());
+
+    out.extend(&(vm.structs.len() as u32).to_be_bytes());
+    for layout in &vm.structs {
+        write_string(&mut out, &layout.name);
+        out.extend(&(layout.fields.len() as u32).to_be_bytes());
+        for field in &layout.fields {
+            write_string(&mut out, field);
+        }
+    }
+    out.extend(&(vm.handlers.len() as u32).to_be_bytes());
+    for handler in &vm.handlers {
+        for value in [handler.start, handler.end, handler.handler, handler.depth] {
+            out.extend(&(value as u64).to_be_bytes());
+        }
+    }
+    out
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], String> {
+        let bytes = self.data.get(self.pos..self.pos + n)
+            .ok_or_else(|| "Snapshot Error: Unexpected end of data".to_string())?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| "Snapshot Error: Invalid UTF-8 in string".to_string())
+    }
+
+    /// Reads a u32 count of items that take at least `min_size` bytes each
+    fn count(&mut self, min_size: usize, what: &str) -> Result<usize, String> {
+        let count = self.u32()? as usize;
+        if count > (self.data.len() - self.pos) / min_size {
+            return Err(format!("Snapshot Error: {} count exceeds snapshot size", what));
+        }
+        Ok(count)
+    }
+
+    fn config(&mut self) -> Result<VmConfig, String> {
+        let overflow = match self.u8()? {
+            0 => OverflowMode::Wrapping,
+            1 => OverflowMode::Saturating,
+            2 => OverflowMode::Trapping,
+            b => return Err(format!("Snapshot Error: Invalid overflow mode {}", b)),
+        };
+        let trap_nan = match self.u8()? {
+            0 => false,
+            1 => true,
+            b => return Err(format!("Snapshot Error: Invalid trap_nan flag {}", b)),
+        };
+        let heap_limit = match self.u8()? {
+            0 => None,
+            1 => Some(self.u64()? as usize),
+            b => return Err(format!("Snapshot Error: Invalid heap limit flag {}", b)),
+        };
+        Ok(VmConfig { overflow, trap_nan, heap_limit })
+    }
+
+    fn structs(&mut self) -> Result<Vec<StructLayout>, String> {
+        // A layout takes at least its name length and field count
+        let count = self.count(8, "Struct")?;
+        let mut structs = Vec::with_capacity(count);
+        for _ in 0..count {
+            let name = self.string()?;
+            let field_count = self.count(4, "Field")?;
+            let fields = (0..field_count).map(|_| self.string()).collect::<Result<_, _>>()?;
+            structs.push(StructLayout { name, fields });
+        }
+        Ok(structs)
+    }
+
+    fn handlers(&mut self) -> Result<Vec<ExceptionHandler>, String> {
+        let count = self.count(32, "Handler")?;
+        let mut handlers = Vec::with_capacity(count);
+        for _ in 0..count {
+            handlers.push(ExceptionHandler {
+                start: self.u64()? as usize,
+                end: self.u64()? as usize,
+                handler: self.u64()? as usize,
+                depth: self.u64()? as usize,
+            });
+        }
+        Ok(handlers)
+    }
+
+    fn values(&mut self) -> Result<Vec<Value>, String> {
+        let count = self.u32()? as usize;
+        // Every value takes at least two bytes, so a larger count is corrupt
+        if count > (self.data.len() - self.pos) / 2 {
+            return Err("Snapshot Error: Value count exceeds snapshot size".to_string());
+        }
+
+        let mut values = Vec::with_capacity(count);
+        for _ in 0..count {
+            let value = match self.u8()? {
+                TAG_INT => Value::Int(i32::from_be_bytes(self.take(4)?.try_into().unwrap())),
+                TAG_FLOAT => Value::Float(f64::from_be_bytes(self.take(8)?.try_into().unwrap())),
+                TAG_CHAR => Value::Char(self.u8()?),
+                TAG_BOOL => match self.u8()? {
+                    0 => Value::Bool(false),
+                    1 => Value::Bool(true),
+                    b => return Err(format!("Snapshot Error: Invalid boolean {}", b)),
+                },
+                TAG_REF => Value::Ref(self.u64()? as usize),
+                TAG_LONG => Value::Long(i64::from_be_bytes(self.take(8)?.try_into().unwrap())),
+                tag => return Err(format!("Snapshot Error: Unknown value tag {}", tag)),
+            };
+            values.push(value);
+        }
+        Ok(values)
+    }
+
+    fn objects(&mut self) -> Result<Vec<Option<Object>>, String> {
+        let count = self.u32()? as usize;
+        // Every slot takes at least its kind byte
+        if count > self.data.len() - self.pos {
+            return Err("Snapshot Error: Object count exceeds snapshot size".to_string());
+        }
+
+        let mut objects = Vec::with_capacity(count);
+        for _ in 0..count {
+            let object = match self.u8()? {
+                KIND_ARRAY => Some(Object::Array(self.values()?)),
+                KIND_RECORD => {
+                    let layout = self.u32()? as usize;
+                    Some(Object::Record { layout, fields: self.values()? })
+                }
+                KIND_FREE => None,
+                kind => return Err(format!("Snapshot Error: Unknown object kind {}", kind)),
+            };
+            objects.push(object);
+        }
+        Ok(objects)
+    }
+}
+
+/// Restores a snapshot into `vm`, which must have been created with the same
+/// code. The config, struct layouts and handler table are replaced by the
+/// saved ones. The machine is left untouched if the snapshot is rejected.
+pub fn restore(vm: &mut VirtualMachine, data: &[u8]) -> Result<(), String> {
+    let mut reader = Reader { data, pos: 0 };
+
+    if reader.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+        return Err("Snapshot Error: Not a Flint snapshot".to_string());
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(format!("Snapshot Error: Unsupported version {} (expected {})", version, VERSION));
+    }
+    let hash = reader.u64()?;
+    let code_len = reader.u32()? as usize;
+    if hash != code_hash(&vm.code) || code_len != vm.code.len() {
+        return Err("Snapshot Error: Snapshot was taken from a different program".to_string());
+    }
+
+    let ip = reader.u64()? as usize;
+    let running = match reader.u8()? {
+        0 => false,
+        1 => true,
+        b => return Err(format!("Snapshot Error: Invalid running flag {}", b)),
+    };
+    let fault = match reader.u8()? {
+        0 => None,
+        1 => Some(reader.string()?),
+        b => return Err(format!("Snapshot Error: Invalid fault flag {}", b)),
+    };
+    let config = reader.config()?;
+    let stack = reader.values()?;
+    let memory = reader.values()?;
+    let constants = reader.values()?;
+    let objects = reader.objects()?;
+    let gc_threshold = reader.u64()? as usize;
+    let structs = reader.structs()?;
+    let handlers = reader.handlers()?;
+    if reader.pos != data.len() {
+        return Err("Snapshot Error: Trailing data after snapshot".to_string());
+    }
+    if ip > vm.code.len() {
+        return Err(format!("Snapshot Error: Instruction pointer {} is outside the code", ip));
+    }
+
+    let heap_values = objects.iter().flatten().flat_map(|object| object.values());
+    let dangling = stack.iter().chain(&memory).chain(&constants).chain(heap_values)
+        .find_map(|v| match *v {
+            Value::Ref(r) if objects.get(r).is_none_or(Option::is_none) => Some(r),
+            _ => None,
+        });
+    if let Some(r) = dangling {
+        return Err(format!("Snapshot Error: Reference {} points outside the heap", r));
+    }
+
+    vm.ip = ip;
+    vm.running = running;
+    vm.stack = stack;
+    vm.memory = memory;
+    vm.constants = constants;
+    vm.heap = Heap::from(objects).with_gc_threshold(gc_threshold);
+    vm.fault = fault;
+    vm.config = config;
+    vm.structs = structs;
+    vm.handlers = handlers;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test_snapshot {
+    use super::*;
+    use crate::vm::assembler::Assembler;
+    use crate::vm::opcodes::op;
+
+    const PROGRAM: &str = "
+        BIPUSH 10
+        STORE 0
+        FPUSH 0.5
+        loop:
+        LOAD 0
+        BIPUSH 1
+        SUB
+        DUP
+        STORE 0
+        BIPUSH 0
+        CMP
+        JG loop
+        HALT
+    ";
+
+    fn program() -> Vec<u8> {
+        Assembler::new().assemble(PROGRAM).unwrap()
+    }
+
+    #[test]
+    fn test_restored_vm_continues_identically() {
+        let mut reference = VirtualMachine::new(program());
+        reference.run().unwrap();
+
+        let mut paused = VirtualMachine::new(program());
+        paused.run_for(17).unwrap();
+        paused.constants.push(Value::Char(b'x'));
+        paused.constants.push(Value::Long(i64::MIN));
+        paused.constants.push(Value::Bool(true));
+        let data = save(&paused);
+
+        let mut resumed = VirtualMachine::new(program());
+        restore(&mut resumed, &data).unwrap();
+        assert_eq!(resumed.ip, paused.ip);
+        assert_eq!(resumed.stack, paused.stack);
+        assert_eq!(resumed.constants, vec![Value::Char(b'x'), Value::Long(i64::MIN), Value::Bool(true)]);
+
+        resumed.run().unwrap();
+        assert_eq!(resumed.ip, reference.ip);
+        assert_eq!(resumed.stack, reference.stack);
+        assert_eq!(resumed.memory, reference.memory);
+        assert!(!resumed.running);
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let config = VmConfig { overflow: OverflowMode::Trapping, trap_nan: true, heap_limit: Some(64) };
+        let mut vm = VirtualMachine::with_config(program(), config);
+        vm.structs = vec![StructLayout { name: "Point".to_string(), fields: vec!["x".to_string(), "y".to_string()] }];
+        vm.handlers = vec![ExceptionHandler { start: 2, end: 9, handler: 11, depth: 1 }];
+        vm.heap = Heap::new().with_gc_threshold(300);
+        let data = save(&vm);
+
+        let mut resumed = VirtualMachine::new(program());
+        restore(&mut resumed, &data).unwrap();
+        assert_eq!(resumed.config, config);
+        assert_eq!(resumed.structs, vm.structs);
+        assert_eq!(resumed.handlers, vm.handlers);
+        assert_eq!(resumed.heap.gc_threshold(), 300);
+
+        // The overflow mode follows the 30-byte header, the running flag and the fault flag
+        let mut bad_mode = data.clone();
+        bad_mode[32] = 9;
+        assert_eq!(restore(&mut resumed, &bad_mode), Err("Snapshot Error: Invalid overflow mode 9".to_string()));
+    }
+
+    #[test]
+    fn test_fault_round_trips() {
+        let mut vm = VirtualMachine::new(vec![op::POP, op::HALT]);
+        assert!(vm.run().is_err());
+        let data = save(&vm);
+
+        let mut resumed = VirtualMachine::new(vec![op::POP, op::HALT]);
+        resumed.fault = Some("stale".to_string());
+        restore(&mut resumed, &data).unwrap();
+        assert_eq!(resumed.fault, vm.fault);
+        assert!(!resumed.running);
+
+        // A clean snapshot clears an earlier fault
+        restore(&mut resumed, &save(&VirtualMachine::new(vec![op::POP, op::HALT]))).unwrap();
+        assert_eq!(resumed.fault, None);
+    }
+
+    #[test]
+    fn test_snapshot_starts_with_header() {
+        let data = save(&VirtualMachine::new(program()));
+        assert_eq!(&data[..8], b"FLNTSNAP");
+        assert_eq!(u16::from_be_bytes([data[8], data[9]]), VERSION);
+    }
+
+    #[test]
+    fn test_rejects_snapshot_of_other_program() {
+        let data = save(&VirtualMachine::new(program()));
+        let mut other = VirtualMachine::new(vec![0, 1]);
+
+        let err = restore(&mut other, &data).unwrap_err();
+        assert!(err.contains("different program"));
+    }
+
+    #[test]
+    fn test_rejects_bad_magic_version_and_truncation() {
+        let data = save(&VirtualMachine::new(program()));
+        let mut vm = VirtualMachine::new(program());
+
+        let mut bad_magic = data.clone();
+        bad_magic[0] = b'X';
+        assert!(restore(&mut vm, &bad_magic).unwrap_err().contains("Not a Flint snapshot"));
+
+        let mut bad_version = data.clone();
+        bad_version[9] = 99;
+        assert!(restore(&mut vm, &bad_version).unwrap_err().contains("Unsupported version 99"));
+
+        assert!(restore(&mut vm, &data[..data.len() - 1]).unwrap_err().contains("Unexpected end"));
+        assert_eq!(vm.ip, 0, "A rejected snapshot must not modify the VM");
+    }
+
+    #[test]
+    fn test_heap_round_trips() {
+        let mut vm = VirtualMachine::new(program());
+        let inner = vm.heap.alloc(Object::Array(vec![Value::Float(1.5)]));
+        let record = vm.heap.alloc(Object::Record { layout: 2, fields: vec![Value::Bool(true)] });
+        let outer = vm.heap.alloc(Object::Array(vec![Value::Ref(inner), Value::Int(7), Value::Ref(record)]));
+        vm.stack.push(Value::Ref(outer));
+        let data = save(&vm);
+
+        let mut resumed = VirtualMachine::new(program());
+        restore(&mut resumed, &data).unwrap();
+        assert!(resumed.heap.objects().eq(vm.heap.objects()));
+        assert_eq!(resumed.stack, vec![Value::Ref(outer)]);
+    }
+
+    #[test]
+    fn test_free_slots_round_trip() {
+        let mut vm = VirtualMachine::new(program());
+        vm.heap.alloc(Object::Array(vec![]));
+        let kept = vm.heap.alloc(Object::Array(vec![Value::Int(1)]));
+        vm.stack.push(Value::Ref(kept));
+        vm.collect_garbage();
+        let data = save(&vm);
+
+        let mut resumed = VirtualMachine::new(program());
+        restore(&mut resumed, &data).unwrap();
+        assert!(resumed.heap.get(0).is_none());
+        assert_eq!(resumed.heap.get(kept), vm.heap.get(kept));
+        assert_eq!(resumed.heap.alloc(Object::Array(vec![])), 0, "Free slots are reused after restore");
+
+        // A reference to a free slot is dangling
+        vm.memory.push(Value::Ref(0));
+        let err = restore(&mut resumed, &save(&vm)).unwrap_err();
+        assert_eq!(err, "Snapshot Error: Reference 0 points outside the heap");
+    }
+
+    #[test]
+    fn test_rejects_dangling_reference() {
+        let mut vm = VirtualMachine::new(program());
+        vm.memory.push(Value::Ref(3));
+        let data = save(&vm);
+
+        let mut resumed = VirtualMachine::new(program());
+        assert_eq!(
+            restore(&mut resumed, &data),
+            Err("Snapshot Error: Reference 3 points outside the heap".to_string())
+        );
+    }
+
+    #[test]
+    fn test_code_hash_is_fnv1a() {
+        assert_eq!(code_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(code_hash(b"a"), 0xaf63dc4c8601ec8c);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::vm::decoder::{decode_at, Operand};
+use crate::vm::runner::Value;
+use std::io::Write;
+use std::ops::Range;
+
+/// Writes one line per executed instruction: its address, mnemonic and
+/// operand, and the top of the stack before and after it ran.
+pub struct Tracer {
+    sink: Box<dyn Write>,
+    range: Option<Range<usize>>,
+}
+
+impl Tracer {
+    pub fn new(sink: Box<dyn Write>) -> Self {
+        Self { sink, range: None }
+    }
+
+    /// Only trace instructions whose address falls inside `range`.
+    pub fn with_range(mut self, range: Range<usize>) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    pub fn traces(&self, addr: usize) -> bool {
+        self.range.as_ref().is_none_or(|r| r.contains(&addr))
+    }
+
+    /// Logs the instruction at `addr`. `fault` is the runtime error it raised, if any.
+    pub fn record(
+        &mut self,
+        code: &[u8],
+        addr: usize,
+        before: Option<Value>,
+        after: Option<Value>,
+        fault: Option<&String>,
+    ) {
+        let (name, operand) = match decode_at(code, addr) {
+            Ok(ins) => (ins.name(), ins.operand),
+            Err(_) => ("UNKNOWN", Operand::None),
+        };
+
+        let mut line = format!(
+            "{:04X}: {:<10} {:<10} {} -> {}",
+            addr,
+            name,
+            operand.to_string(),
+            format_top(before),
+            format_top(after)
+        );
+        if let Some(e) = fault {
+            line.push_str(&format!(" !! {}", e));
+        }
+        // A broken sink must not take the program down with it
+        let _ = writeln!(self.sink, "{}", line);
+    }
+}
+
+fn format_top(value: Option<Value>) -> String {
+    match value {
+        Some(v) => format!("{:?}", v),
+        None => "<empty>".to_string(),
+    }
+}
+
+
+#[cfg(test)]
+mod test_trace {
+    use super::*;
+    use crate::vm::opcodes::op;
+    use crate::vm::runner::VirtualMachine;
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_trace_logs_every_step() {
+        let buf = SharedBuf::default();
+        let mut vm = VirtualMachine::new(vec![op::BIPUSH, 2, op::BIPUSH, 3, op::ADD, op::HALT]);
+        vm.tracer = Some(Tracer::new(Box::new(buf.clone())));
+        vm.run().unwrap();
+
+        let text = buf.text();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines, vec![
+            "0000: BIPUSH     2          <empty> -> Int(2)",
+            "0002: BIPUSH     3          Int(2) -> Int(3)",
+            "0004: ADD                   Int(3) -> Int(5)",
+            "0005: HALT                  Int(5) -> Int(5)",
+        ]);
+    }
+
+    #[test]
+    fn test_trace_respects_address_range() {
+        let buf = SharedBuf::default();
+        let mut vm = VirtualMachine::new(vec![op::NOP, op::NOP, op::NOP, op::HALT]);
+        vm.tracer = Some(Tracer::new(Box::new(buf.clone())).with_range(1..3));
+        vm.run().unwrap();
+
+        let text = buf.text();
+        let addrs: Vec<&str> = text.lines().map(|l| &l[..4]).collect();
+        assert_eq!(addrs, vec!["0001", "0002"]);
+    }
+
+    #[test]
+    fn test_trace_marks_faulting_instruction() {
+        let buf = SharedBuf::default();
+        let mut vm = VirtualMachine::new(vec![op::BIPUSH, 1, op::BIPUSH, 0, op::DIV]);
+        vm.tracer = Some(Tracer::new(Box::new(buf.clone())));
+        assert!(vm.run().is_err());
+
+        let text = buf.text();
+        assert!(text.lines().last().unwrap().ends_with("!! Runtime Error: Division by zero"));
+    }
+}
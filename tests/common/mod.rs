@@ -0,0 +1,29 @@
+use flint::vm::assembler::Assembler;
+use flint::vm::runner::{VirtualMachine, VmConfig};
+
+/// Runs `vm` until it halts or faults and hands it back for inspection. A
+/// fault is left in `VirtualMachine::fault` for the test to check.
+pub fn finish(mut vm: VirtualMachine) -> VirtualMachine {
+    let _ = vm.run();
+    vm
+}
+
+/// Runs `code` with the default configuration, see `finish`
+pub fn run(code: Vec<u8>) -> VirtualMachine {
+    finish(VirtualMachine::new(code))
+}
+
+/// Runs `code` with `config`, see `finish`
+pub fn run_with(code: Vec<u8>, config: VmConfig) -> VirtualMachine {
+    finish(VirtualMachine::with_config(code, config))
+}
+
+/// Assembles `source` into a machine set up with the struct layouts and
+/// exception handlers it declares, ready to run
+pub fn assemble(source: &str) -> VirtualMachine {
+    let mut assembler = Assembler::new();
+    let mut vm = VirtualMachine::new(assembler.assemble(source).unwrap());
+    vm.structs = assembler.structs().to_vec();
+    vm.handlers = assembler.handlers().to_vec();
+    vm
+}
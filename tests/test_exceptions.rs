@@ -0,0 +1,208 @@
+#[allow(dead_code)]
+mod common;
+
+#[cfg(test)]
+mod test_exceptions {
+    use flint::vm::runner::*;
+    use flint::vm::opcodes::*;
+    use flint::vm::history::History;
+    use flint::bytecode;
+    use crate::common::{assemble, finish};
+
+    /// Runs `body` inside a protected range whose handler stores the exception in memory[0]
+    fn caught(body: &str) -> Value {
+        let vm = finish(assemble(&format!("
+            .catch try done handler
+            try:
+            {}
+            done:
+            HALT
+            handler:
+            STORE 0
+            HALT
+        ", body)));
+        assert!(vm.stack.is_empty(), "The stack is unwound to depth 0");
+        vm.memory[0]
+    }
+
+    #[test]
+    fn test_faults_become_exceptions() {
+        assert_eq!(caught("BIPUSH 1\nBIPUSH 0\nDIV"), Value::Int(EXC_DIVISION_BY_ZERO));
+        assert_eq!(caught("BIPUSH 1\nBIPUSH 0\nMOD"), Value::Int(EXC_DIVISION_BY_ZERO));
+        assert_eq!(caught("BIPUSH 1\nFPUSH 1.0\nAND"), Value::Int(EXC_TYPE));
+        assert_eq!(caught("BIPUSH 1\nNEWARRAY\nBIPUSH 5\nALOAD"), Value::Int(EXC_BOUNDS));
+        assert_eq!(caught("LOAD 3"), Value::Int(EXC_BOUNDS));
+    }
+
+    #[test]
+    fn test_arithmetic_traps_become_exceptions() {
+        let source = ".catch 0 done handler\nIPUSH 2147483647\nBIPUSH 1\nADD\ndone:\nHALT\nhandler:\nHALT";
+        let mut machine = assemble(source);
+        machine.config.overflow = OverflowMode::Trapping;
+        assert_eq!(finish(machine).stack, vec![Value::Int(EXC_ARITHMETIC)]);
+
+        let source = ".catch 0 done handler\nFPUSH -1.0\nSQRT\ndone:\nHALT\nhandler:\nHALT";
+        let mut machine = assemble(source);
+        machine.config.trap_nan = true;
+        assert_eq!(finish(machine).stack, vec![Value::Int(EXC_ARITHMETIC)]);
+
+        let source = ".catch 0 done handler\nFPUSH -1.0\nSQRT\nF2I 0\ndone:\nHALT\nhandler:\nHALT";
+        let mut machine = assemble(source);
+        machine.config.overflow = OverflowMode::Trapping;
+        assert_eq!(finish(machine).stack, vec![Value::Int(EXC_ARITHMETIC)]);
+    }
+
+    #[test]
+    fn test_fatal_faults_are_not_caught() {
+        let mut machine = assemble(".catch 0 1 1\nPOP\nHALT");
+        assert_eq!(machine.run(), Err("Stack underflow!".to_string()));
+
+        let handlers = vec![ExceptionHandler { start: 0, end: 10, handler: 0, depth: 0 }];
+        for (code, error) in [
+            (vec![255], "Unknown opcode: 255"),
+            (vec![op::IPUSH, 0], "Runtime Error: Bytecode ended prematurely"),
+        ] {
+            let mut machine = VirtualMachine::new(code);
+            machine.handlers = handlers.clone();
+            assert_eq!(machine.run(), Err(error.to_string()));
+        }
+
+        let config = VmConfig { heap_limit: Some(2), ..VmConfig::default() };
+        let mut machine = VirtualMachine::with_config(bytecode!(BIPUSH 8, NEWARRAY, HALT), config);
+        machine.handlers = handlers;
+        assert!(machine.run().unwrap_err().starts_with("Runtime Error: Heap limit of 2 slots exceeded"));
+    }
+
+    #[test]
+    fn test_throw_passes_its_value() {
+        assert_eq!(caught("IPUSH 404\nTHROW"), Value::Int(404));
+        assert_eq!(caught("FPUSH 2.5\nTHROW"), Value::Float(2.5));
+    }
+
+    #[test]
+    fn test_uncaught_exceptions_are_faults() {
+        let mut machine = VirtualMachine::new(bytecode!(IPUSH 404, THROW));
+        assert_eq!(machine.run(), Err("Runtime Error: Uncaught exception 404".to_string()));
+
+        // A fault after the protected range is not caught
+        let mut machine = assemble(".catch 0 2 4\nBIPUSH 0\nBIPUSH 1\nBIPUSH 0\nDIV");
+        assert_eq!(machine.run(), Err("Runtime Error: Division by zero".to_string()));
+    }
+
+    #[test]
+    fn test_stack_is_unwound_to_handler_depth() {
+        let vm = finish(assemble("
+            .catch try done handler 2
+            BIPUSH 7
+            BIPUSH 8
+            try:
+            BIPUSH 9
+            BIPUSH 10
+            BIPUSH 0
+            DIV
+            done:
+            HALT
+            handler:
+            HALT
+        "));
+        assert_eq!(vm.stack, vec![Value::Int(7), Value::Int(8), Value::Int(EXC_DIVISION_BY_ZERO)]);
+        assert!(vm.fault.is_none());
+    }
+
+    #[test]
+    fn test_inner_handler_wins_and_can_rethrow() {
+        let vm = finish(assemble("
+            .catch inner inner_end inner_handler
+            .catch outer outer_end outer_handler
+            outer:
+            inner:
+            BIPUSH 1
+            BIPUSH 0
+            DIV
+            inner_end:
+            HALT
+            inner_handler:
+            BIPUSH 100
+            ADD
+            THROW
+            outer_end:
+            HALT
+            outer_handler:
+            STORE 0
+            HALT
+        "));
+        assert_eq!(vm.memory[0], Value::Int(100 + EXC_DIVISION_BY_ZERO));
+    }
+
+    #[test]
+    fn test_innermost_handler_wins_regardless_of_order() {
+        let vm = finish(assemble("
+            .catch outer done outer_handler
+            .catch inner done inner_handler
+            outer:
+            NOP
+            inner:
+            BIPUSH 1
+            BIPUSH 0
+            DIV
+            done:
+            HALT
+            outer_handler:
+            BIPUSH 1
+            STORE 0
+            HALT
+            inner_handler:
+            BIPUSH 2
+            STORE 0
+            HALT
+        "));
+        assert_eq!(vm.memory[0], Value::Int(2));
+    }
+
+    #[test]
+    fn test_handler_deeper_than_stack_is_fatal() {
+        // The exception is raised with one value on the stack, below the depth of 2
+        let mut machine = assemble(".catch try done handler 2\nBIPUSH 7\ntry:\nBIPUSH 1\nBIPUSH 0\nDIV\ndone:\nHALT\nhandler:\nHALT");
+        assert_eq!(machine.run(), Err("Runtime Error: Handler at 8 expects a stack depth of 2, found 1".to_string()));
+        assert_eq!(machine.ip, 7);
+    }
+
+    #[test]
+    fn test_retry_loop() {
+        // Divides 12 by a divisor that starts at 0 and is bumped after every failure
+        let vm = finish(assemble("
+            .catch try done handler
+            BIPUSH 0
+            STORE 0
+            try:
+            BIPUSH 12
+            LOAD 0
+            DIV
+            done:
+            HALT
+            handler:
+            POP
+            LOAD 0
+            BIPUSH 3
+            ADD
+            STORE 0
+            JMP try
+        "));
+        assert_eq!(vm.stack, vec![Value::Int(4)]);
+    }
+
+    #[test]
+    fn test_step_back_undoes_catch() {
+        let mut vm = assemble(".catch 0 7 7\nBIPUSH 5\nBIPUSH 1\nBIPUSH 0\nDIV\nHALT");
+        vm.history = Some(History::new());
+        for _ in 0..4 {
+            vm.step().unwrap();
+        }
+        assert_eq!(vm.stack, vec![Value::Int(EXC_DIVISION_BY_ZERO)]);
+        assert_eq!(vm.ip, 7);
+
+        assert!(vm.step_back());
+        assert_eq!(vm.stack, vec![Value::Int(5), Value::Int(1), Value::Int(0)]);
+        assert_eq!(vm.ip, 6);
+    }
+}
@@ -0,0 +1,141 @@
+#[cfg(test)]
+mod test_garbage_collector {
+    use flint::vm::runner::*;
+    use flint::vm::heap::Object;
+    use flint::vm::history::History;
+    use flint::vm::opcodes::*;
+    use flint::vm::assembler::Assembler;
+    use flint::bytecode;
+
+    fn limited(code: Vec<u8>, slots: usize) -> VirtualMachine {
+        VirtualMachine::with_config(code, VmConfig { heap_limit: Some(slots), ..VmConfig::default() })
+    }
+
+    #[test]
+    fn test_alloc_takes_values_from_stack() {
+        let mut vm = VirtualMachine::new(bytecode!(BIPUSH 7, FPUSH 0.5, BIPUSH 1, BIPUSH 3, ALLOC, HALT));
+        vm.execute();
+
+        assert_eq!(vm.stack, vec![Value::Ref(0)]);
+        assert_eq!(vm.heap.get(0), Some(&Object::Array(vec![Value::Int(7), Value::Float(0.5), Value::Int(1)])));
+    }
+
+    #[test]
+    fn test_alloc_errors() {
+        let mut vm = VirtualMachine::new(bytecode!(BIPUSH 1, BIPUSH 2, ALLOC));
+        assert_eq!(vm.run(), Err("Runtime Error: ALLOC of 2 values with 1 on the stack".to_string()));
+
+        let mut vm = VirtualMachine::new(bytecode!(IPUSH -1, ALLOC));
+        assert_eq!(vm.run(), Err("Runtime Error: ALLOC of -1 values with 0 on the stack".to_string()));
+
+        let mut vm = VirtualMachine::new(bytecode!(FPUSH 1.0, ALLOC));
+        assert_eq!(vm.run(), Err("Type error: ALLOC expects an integer count".to_string()));
+    }
+
+    #[test]
+    fn test_forced_gc_frees_unreachable_objects() {
+        let mut vm = VirtualMachine::new(bytecode!(
+            BIPUSH 4, NEWARRAY, POP,
+            BIPUSH 2, NEWARRAY, STORE 0,
+            GC, HALT
+        ));
+        vm.execute();
+
+        assert_eq!(vm.heap.len(), 1);
+        assert!(vm.heap.get(0).is_none());
+        assert_eq!(vm.memory, vec![Value::Ref(1)]);
+        assert_eq!(vm.heap.stats.collections, 1);
+        assert_eq!(vm.heap.stats.objects_freed, 1);
+        assert_eq!(vm.heap.stats.slots_freed, 5);
+    }
+
+    #[test]
+    fn test_nested_and_constant_references_are_roots() {
+        let mut vm = VirtualMachine::new(bytecode!(BIPUSH 1, NEWARRAY, BIPUSH 1, ALLOC, GC, HALT));
+        let constant = vm.heap.alloc(Object::Array(vec![]));
+        vm.constants.push(Value::Ref(constant));
+        vm.execute();
+
+        assert_eq!(vm.heap.len(), 3);
+        assert_eq!(vm.heap.stats.objects_freed, 0);
+    }
+
+    #[test]
+    fn test_loop_allocation_is_reclaimed() {
+        // Allocates 1000 arrays of 10 values, keeping only the latest one
+        let source = "
+            IPUSH 1000
+            STORE 0
+            loop:
+            BIPUSH 10
+            NEWARRAY
+            STORE 1
+            LOAD 0
+            BIPUSH 1
+            SUB
+            DUP
+            STORE 0
+            BIPUSH 0
+            CMP
+            JG loop
+            HALT
+        ";
+        let code = Assembler::new().assemble(source).unwrap();
+        let mut vm = limited(code, 100);
+        vm.execute();
+
+        // At most 9 arrays of 11 slots fit, so a collection runs every few iterations
+        assert!(vm.heap.len() <= 9);
+        assert_eq!(vm.heap.stats.objects_freed as usize + vm.heap.len(), 1000);
+        assert!(vm.heap.stats.collections >= 100);
+        assert!(vm.heap.stats.peak_slots <= 100);
+    }
+
+    #[test]
+    fn test_automatic_collection_without_limit() {
+        let mut code = Vec::new();
+        for _ in 0..200 {
+            code.extend(bytecode!(BIPUSH 10, NEWARRAY, POP));
+        }
+        let mut vm = VirtualMachine::new(code);
+        vm.run().unwrap();
+
+        assert!(vm.heap.stats.collections > 0);
+        assert!(vm.heap.len() < 200);
+    }
+
+    #[test]
+    fn test_heap_limit_exceeded_by_live_data() {
+        let mut vm = limited(bytecode!(BIPUSH 5, NEWARRAY, BIPUSH 5, NEWARRAY), 10);
+        assert_eq!(
+            vm.run(),
+            Err("Runtime Error: Heap limit of 10 slots exceeded (6 in use, 6 requested)".to_string())
+        );
+        assert_eq!(vm.heap.stats.collections, 1);
+    }
+
+    #[test]
+    fn test_heap_limit_checked_before_building_array() {
+        let mut vm = limited(bytecode!(IPUSH 2000000000, NEWARRAY), 100);
+        assert_eq!(
+            vm.run(),
+            Err("Runtime Error: Heap limit of 100 slots exceeded (0 in use, 2000000001 requested)".to_string())
+        );
+        assert!(vm.heap.is_empty());
+    }
+
+    #[test]
+    fn test_history_keeps_popped_references_alive() {
+        let mut vm = VirtualMachine::new(bytecode!(BIPUSH 3, NEWARRAY, POP, GC, HALT));
+        vm.history = Some(History::new());
+        vm.execute();
+        assert_eq!(vm.heap.len(), 1, "The popped array can still be restored by stepping back");
+
+        // Back over HALT, GC and POP
+        for _ in 0..3 {
+            vm.step_back();
+        }
+        assert_eq!(vm.stack, vec![Value::Ref(0)]);
+        assert!(vm.heap.get(0).is_some());
+    }
+}
@@ -0,0 +1,119 @@
+#[allow(dead_code)]
+mod common;
+
+#[cfg(test)]
+mod test_indirect_memory {
+    use flint::vm::runner::*;
+    use flint::vm::opcodes::*;
+    use flint::vm::assembler::Assembler;
+    use flint::bytecode;
+    use crate::common::run;
+
+    #[test]
+    fn test_storei_and_loadi() {
+        let vm = run(bytecode!(BIPUSH 2, BIPUSH 42, STOREI, BIPUSH 2, LOADI, HALT));
+        assert_eq!(vm.stack, vec![Value::Int(42)]);
+        assert_eq!(vm.memory, vec![Value::Int(0), Value::Int(0), Value::Int(42)]);
+    }
+
+    #[test]
+    fn test_indirect_and_direct_access_share_memory() {
+        let vm = run(bytecode!(FPUSH 1.5, STORE 3, LPUSH 3, LOADI, IPUSH 1, LOAD 3, STOREI, LOAD 1, HALT));
+        assert_eq!(vm.stack, vec![Value::Float(1.5), Value::Float(1.5)]);
+    }
+
+    #[test]
+    fn test_loadi_out_of_bounds() {
+        let mut vm = VirtualMachine::new(bytecode!(BIPUSH 0, BIPUSH 1, STOREI, BIPUSH 5, LOADI));
+        assert_eq!(vm.run(), Err("Runtime Error: Access to uninitialized or out-of-bounds address: 5".to_string()));
+    }
+
+    #[test]
+    fn test_address_errors() {
+        let mut vm = VirtualMachine::new(bytecode!(IPUSH -1, LOADI));
+        assert_eq!(vm.run(), Err("Runtime Error: Invalid address -1 in LOADI".to_string()));
+
+        let mut vm = VirtualMachine::new(bytecode!(FPUSH 1.0, BIPUSH 0, STOREI));
+        assert_eq!(vm.run(), Err("Type error: STOREI expects an integer address".to_string()));
+    }
+
+    #[test]
+    fn test_addr_pushes_address() {
+        let vm = run(bytecode!(ADDR 7, HALT));
+        assert_eq!(vm.stack, vec![Value::Int(7)]);
+    }
+
+    #[test]
+    fn test_linked_list_in_memory() {
+        // Nodes are [value, next] pairs; next = -1 ends the list. Sums 3 -> 1 -> 4.
+        let source = "
+            .data head
+            .data sum
+            .data nodes 6
+
+            ; node 0: value 3, next = node 2
+            ADDR nodes
+            BIPUSH 3
+            STOREI
+            ADDR nodes
+            BIPUSH 1
+            ADD
+            ADDR nodes
+            BIPUSH 4
+            ADD
+            STOREI
+            ; node 2: value 1, next = node 1
+            ADDR nodes
+            BIPUSH 4
+            ADD
+            BIPUSH 1
+            STOREI
+            ADDR nodes
+            BIPUSH 5
+            ADD
+            ADDR nodes
+            BIPUSH 2
+            ADD
+            STOREI
+            ; node 1: value 4, end of list
+            ADDR nodes
+            BIPUSH 2
+            ADD
+            BIPUSH 4
+            STOREI
+            ADDR nodes
+            BIPUSH 3
+            ADD
+            IPUSH -1
+            STOREI
+
+            ADDR nodes
+            STORE head
+            BIPUSH 0
+            STORE sum
+            walk:
+            LOAD sum
+            LOAD head
+            LOADI
+            ADD
+            STORE sum
+            LOAD head
+            BIPUSH 1
+            ADD
+            LOADI
+            DUP
+            STORE head
+            IPUSH -1
+            CMP
+            JNE walk
+            LOAD sum
+            HALT
+        ";
+        let mut assembler = Assembler::new();
+        let code = assembler.assemble(source).unwrap();
+        let vm = run(code);
+
+        assert_eq!(vm.stack, vec![Value::Int(8)]);
+        assert_eq!(vm.memory[assembler.data_symbols()["sum"] as usize], Value::Int(8));
+    }
+}
@@ -0,0 +1,84 @@
+#[allow(dead_code)]
+mod common;
+
+#[cfg(test)]
+mod test_integer_overflow {
+    use flint::vm::runner::*;
+    use flint::vm::opcodes::*;
+    use flint::bytecode;
+    use crate::common::run_with;
+
+    fn config(overflow: OverflowMode) -> VmConfig {
+        VmConfig { overflow, ..VmConfig::default() }
+    }
+
+    fn overflowing_programs() -> Vec<(&'static str, Vec<u8>)> {
+        vec![
+            ("ADD", bytecode!(IPUSH 2147483647, BIPUSH 1, ADD)),
+            ("SUB", bytecode!(IPUSH -2147483648, BIPUSH 1, SUB)),
+            ("MUL", bytecode!(IPUSH 65536, IPUSH 65536, MUL)),
+            ("NEG", bytecode!(IPUSH -2147483648, NEG)),
+            ("DIV", bytecode!(IPUSH -2147483648, IPUSH -1, DIV)),
+            ("MOD", bytecode!(IPUSH -2147483648, IPUSH -1, MOD)),
+        ]
+    }
+
+    #[test]
+    fn test_default_mode_is_wrapping() {
+        assert_eq!(VmConfig::default().overflow, OverflowMode::Wrapping);
+
+        let mut vm = VirtualMachine::new(bytecode!(IPUSH 2147483647, BIPUSH 1, ADD, HALT));
+        vm.execute();
+        assert_eq!(vm.stack, vec![Value::Int(i32::MIN)]);
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let expected = [i32::MIN, i32::MAX, 0, i32::MIN, i32::MIN, 0];
+        for ((name, code), want) in overflowing_programs().into_iter().zip(expected) {
+            assert_eq!(run_with(code, config(OverflowMode::Wrapping)).stack, vec![Value::Int(want)], "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_saturating() {
+        let expected = [i32::MAX, i32::MIN, i32::MAX, i32::MAX, i32::MAX, 0];
+        for ((name, code), want) in overflowing_programs().into_iter().zip(expected) {
+            assert_eq!(run_with(code, config(OverflowMode::Saturating)).stack, vec![Value::Int(want)], "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_trapping() {
+        for (name, code) in overflowing_programs() {
+            assert_eq!(
+                run_with(code, config(OverflowMode::Trapping)).fault,
+                Some(format!("Runtime Error: Integer overflow in {}", name))
+            );
+        }
+    }
+
+    #[test]
+    fn test_in_range_results_are_unaffected() {
+        for mode in [OverflowMode::Wrapping, OverflowMode::Saturating, OverflowMode::Trapping] {
+            let code = bytecode!(
+                IPUSH 2147483646, BIPUSH 1, ADD,
+                IPUSH -7, BIPUSH 2, DIV,
+                IPUSH -7, BIPUSH 2, MOD,
+                HALT
+            );
+            assert_eq!(
+                run_with(code, config(mode)).stack,
+                vec![Value::Int(i32::MAX), Value::Int(-3), Value::Int(-1)]
+            );
+        }
+    }
+
+    #[test]
+    fn test_division_by_zero_still_reported_in_every_mode() {
+        for mode in [OverflowMode::Wrapping, OverflowMode::Saturating, OverflowMode::Trapping] {
+            let vm = run_with(bytecode!(BIPUSH 1, BIPUSH 0, DIV), config(mode));
+            assert_eq!(vm.fault, Some("Runtime Error: Division by zero".to_string()));
+        }
+    }
+}
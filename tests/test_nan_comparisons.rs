@@ -0,0 +1,132 @@
+#[allow(dead_code)]
+mod common;
+
+#[cfg(test)]
+mod test_nan_comparisons {
+    use flint::vm::runner::*;
+    use flint::vm::opcodes::*;
+    use flint::bytecode;
+    use crate::common::run_with;
+
+    /// Compares `a` with `b` and runs `jump`; returns whether it jumped.
+    /// Layout: FPUSH(9) FPUSH(9) CMP(1) Jcc(5) -> 24, BIPUSH 0 (24..26), HALT (26)
+    fn jumps(jump: u8, a: f64, b: f64) -> bool {
+        let mut code = bytecode!(FPUSH a, FPUSH b, CMP);
+        code.push(jump);
+        code.extend(&26u32.to_be_bytes());
+        code.extend(bytecode!(BIPUSH 0, HALT));
+
+        let mut vm = VirtualMachine::new(code);
+        vm.execute();
+        vm.stack.is_empty()
+    }
+
+    fn trapping() -> VmConfig {
+        VmConfig { trap_nan: true, ..VmConfig::default() }
+    }
+
+    #[test]
+    fn test_cmp_results() {
+        let mut vm = VirtualMachine::new(bytecode!(
+            FPUSH 1.0, FPUSH 2.0, CMP,
+            FPUSH 2.0, FPUSH 2.0, CMP,
+            FPUSH 3.0, FPUSH 2.0, CMP,
+            FPUSH f64::NAN, FPUSH 2.0, CMP,
+            BIPUSH 2, FPUSH f64::NAN, CMP,
+            HALT
+        ));
+        vm.execute();
+        assert_eq!(vm.stack, vec![
+            Value::Int(CMP_LESS),
+            Value::Int(CMP_EQUAL),
+            Value::Int(CMP_GREATER),
+            Value::Int(CMP_UNORDERED),
+            Value::Int(CMP_UNORDERED),
+        ]);
+    }
+
+    #[test]
+    fn test_all_conditional_jumps_follow_ieee() {
+        let nan = f64::NAN;
+        // (jump, less, equal, greater, unordered)
+        let table = [
+            (op::JL,  true,  false, false, false),
+            (op::JLE, true,  true,  false, false),
+            (op::JG,  false, false, true,  false),
+            (op::JGE, false, true,  true,  false),
+            (op::JE,  false, true,  false, false),
+            (op::JNE, true,  false, true,  true),
+            (op::JU,  false, false, false, true),
+        ];
+        for (jump, less, equal, greater, unordered) in table {
+            let name = op::get_info(jump).unwrap().name;
+            assert_eq!(jumps(jump, 1.0, 2.0), less, "{} on less", name);
+            assert_eq!(jumps(jump, 2.0, 2.0), equal, "{} on equal", name);
+            assert_eq!(jumps(jump, 3.0, 2.0), greater, "{} on greater", name);
+            assert_eq!(jumps(jump, nan, 2.0), unordered, "{} on NaN left", name);
+            assert_eq!(jumps(jump, 2.0, nan), unordered, "{} on NaN right", name);
+            assert_eq!(jumps(jump, nan, nan), unordered, "{} on NaN both", name);
+        }
+    }
+
+    #[test]
+    fn test_infinities_are_ordered() {
+        assert!(jumps(op::JL, f64::NEG_INFINITY, f64::INFINITY));
+        assert!(jumps(op::JE, f64::INFINITY, f64::INFINITY));
+        assert!(!jumps(op::JU, f64::INFINITY, f64::INFINITY));
+    }
+
+    #[test]
+    fn test_isnan() {
+        let mut vm = VirtualMachine::new(bytecode!(
+            FPUSH f64::NAN, ISNAN,
+            FPUSH 1.5, ISNAN,
+            BIPUSH 0, ISNAN,
+            HALT
+        ));
+        vm.execute();
+        assert_eq!(vm.stack, vec![Value::Bool(true), Value::Bool(false), Value::Bool(false)]);
+
+        let mut vm = VirtualMachine::new(bytecode!(BIPUSH 1, BIPUSH 1, EQ, ISNAN));
+        assert!(vm.run().unwrap_err().contains("ISNAN only supported for numeric types"));
+    }
+
+    #[test]
+    fn test_nan_propagates_by_default() {
+        let mut vm = VirtualMachine::new(bytecode!(FPUSH -1.0, SQRT, BIPUSH 1, ADD, ISNAN, HALT));
+        vm.execute();
+        assert_eq!(vm.stack, vec![Value::Bool(true)]);
+    }
+
+    #[test]
+    fn test_trap_on_nan_production() {
+        assert_eq!(
+            run_with(bytecode!(FPUSH -1.0, SQRT), trapping()).fault,
+            Some("Runtime Error: SQRT produced NaN".to_string())
+        );
+        assert_eq!(
+            run_with(bytecode!(FPUSH f64::INFINITY, FPUSH f64::INFINITY, SUB), trapping()).fault,
+            Some("Runtime Error: SUB produced NaN".to_string())
+        );
+    }
+
+    #[test]
+    fn test_trap_ignores_nan_that_is_only_moved() {
+        // Pushing, duplicating and testing a NaN does not produce one
+        let stack = run_with(bytecode!(FPUSH f64::NAN, DUP, ISNAN, HALT), trapping()).stack;
+        assert_eq!(stack[1], Value::Bool(true));
+
+        let stack = run_with(bytecode!(FPUSH 4.0, SQRT, HALT), trapping()).stack;
+        assert_eq!(stack, vec![Value::Float(2.0)]);
+    }
+
+    #[test]
+    fn test_trap_ignores_nan_passed_through_arithmetic() {
+        // An existing NaN operand is propagated, not produced
+        let stack = run_with(bytecode!(FPUSH f64::NAN, NEG, HALT), trapping()).stack;
+        assert!(matches!(stack[..], [Value::Float(v)] if v.is_nan()));
+
+        let stack = run_with(bytecode!(BIPUSH 1, FPUSH f64::NAN, ADD, FPUSH 2.0, POW, HALT), trapping()).stack;
+        assert!(matches!(stack[..], [Value::Float(v)] if v.is_nan()));
+    }
+}
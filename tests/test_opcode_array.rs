@@ -0,0 +1,170 @@
+#[allow(dead_code)]
+mod common;
+
+#[cfg(test)]
+mod test_opcode_array {
+    use flint::vm::runner::*;
+    use flint::vm::heap::Object;
+    use flint::vm::opcodes::*;
+    use flint::vm::assembler::Assembler;
+    use flint::bytecode;
+    use crate::common::run;
+
+    #[test]
+    fn test_newarray_is_zero_filled() {
+        let vm = run(bytecode!(BIPUSH 3, NEWARRAY, DUP, ALEN, HALT));
+        assert_eq!(vm.stack, vec![Value::Ref(0), Value::Int(3)]);
+        assert_eq!(vm.heap.get(0), Some(&Object::Array(vec![Value::Int(0); 3])));
+    }
+
+    #[test]
+    fn test_store_and_load() {
+        let vm = run(bytecode!(
+            BIPUSH 2, NEWARRAY, STORE 0,
+            LOAD 0, BIPUSH 1, FPUSH 2.5, ASTORE,
+            LOAD 0, LPUSH 1, ALOAD,
+            LOAD 0, BIPUSH 0, ALOAD,
+            HALT
+        ));
+        assert_eq!(vm.stack, vec![Value::Float(2.5), Value::Int(0)]);
+    }
+
+    #[test]
+    fn test_arrays_are_shared_by_reference() {
+        let vm = run(bytecode!(BIPUSH 1, NEWARRAY, DUP, BIPUSH 0, BIPUSH 42, ASTORE, BIPUSH 0, ALOAD, HALT));
+        assert_eq!(vm.stack, vec![Value::Int(42)]);
+    }
+
+    #[test]
+    fn test_index_out_of_bounds() {
+        assert_eq!(
+            run(bytecode!(BIPUSH 2, NEWARRAY, BIPUSH 2, ALOAD)).fault,
+            Some("Runtime Error: Array index 2 out of bounds for length 2".to_string())
+        );
+        assert_eq!(
+            run(bytecode!(BIPUSH 2, NEWARRAY, IPUSH -1, BIPUSH 0, ASTORE)).fault,
+            Some("Runtime Error: Array index -1 out of bounds for length 2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_type_errors() {
+        assert_eq!(run(bytecode!(BIPUSH 1, BIPUSH 0, ALOAD)).fault, Some("Type error: ALOAD expects an array reference".to_string()));
+        assert_eq!(run(bytecode!(BIPUSH 1, NEWARRAY, FPUSH 0.0, ALOAD)).fault, Some("Type error: ALOAD expects an integer index".to_string()));
+        assert_eq!(run(bytecode!(FPUSH 2.0, NEWARRAY)).fault, Some("Type error: NEWARRAY expects an integer length".to_string()));
+        assert_eq!(run(bytecode!(IPUSH -3, NEWARRAY)).fault, Some("Runtime Error: Negative array length -3".to_string()));
+    }
+
+    #[test]
+    fn test_length_too_large() {
+        assert_eq!(
+            run(bytecode!(LPUSH 9000000000000000000i64, NEWARRAY)).fault,
+            Some("Runtime Error: Array length 9000000000000000000 exceeds the maximum of 2147483647".to_string())
+        );
+    }
+
+    #[test]
+    fn test_empty_array() {
+        let vm = run(bytecode!(BIPUSH 0, NEWARRAY, ALEN, HALT));
+        assert_eq!(vm.stack, vec![Value::Int(0)]);
+        assert_eq!(run(bytecode!(BIPUSH 0, NEWARRAY, BIPUSH 0, ALOAD)).fault, Some("Runtime Error: Array index 0 out of bounds for length 0".to_string()));
+    }
+
+    #[test]
+    fn test_format_value() {
+        let mut vm = run(bytecode!(
+            BIPUSH 3, NEWARRAY, STORE 0,
+            LOAD 0, BIPUSH 0, BIPUSH 7, ASTORE,
+            LOAD 0, BIPUSH 1, FPUSH 0.5, ASTORE,
+            LOAD 0, BIPUSH 2, LOAD 0, ASTORE,
+            HALT
+        ));
+        assert_eq!(vm.format_value(Value::Ref(0), &mut Vec::new()), "[7, 0.50, [...]]");
+
+        vm.heap.alloc(Object::Array(vec![Value::Ref(0), Value::Char(b'x')]));
+        assert_eq!(vm.format_value(Value::Ref(1), &mut Vec::new()), "[[7, 0.50, [...]], x]");
+    }
+
+    #[test]
+    fn test_bubble_sort() {
+        // Fills a[i] = 5 - i for i in 0..5, then sorts it in place
+        let source = "
+            BIPUSH 5
+            NEWARRAY
+            STORE 0
+            BIPUSH 0
+            STORE 1
+            fill:
+            LOAD 0
+            LOAD 1
+            BIPUSH 5
+            LOAD 1
+            SUB
+            ASTORE
+            LOAD 1
+            BIPUSH 1
+            ADD
+            DUP
+            STORE 1
+            BIPUSH 5
+            CMP
+            JL fill
+
+            outer:
+            BIPUSH 0
+            STORE 2
+            BIPUSH 0
+            STORE 1
+            inner:
+            LOAD 0
+            LOAD 1
+            ALOAD
+            LOAD 0
+            LOAD 1
+            BIPUSH 1
+            ADD
+            ALOAD
+            LE
+            JT next
+            LOAD 0
+            LOAD 1
+            ALOAD
+            STORE 3
+            LOAD 0
+            LOAD 1
+            LOAD 0
+            LOAD 1
+            BIPUSH 1
+            ADD
+            ALOAD
+            ASTORE
+            LOAD 0
+            LOAD 1
+            BIPUSH 1
+            ADD
+            LOAD 3
+            ASTORE
+            BIPUSH 1
+            STORE 2
+            next:
+            LOAD 1
+            BIPUSH 1
+            ADD
+            DUP
+            STORE 1
+            BIPUSH 4
+            CMP
+            JL inner
+            LOAD 2
+            BIPUSH 1
+            CMP
+            JE outer
+            HALT
+        ";
+        let code = Assembler::new().assemble(source).unwrap();
+        let vm = run(code);
+
+        let sorted: Vec<Value> = (1..=5).map(Value::Int).collect();
+        assert_eq!(vm.heap.get(0), Some(&Object::Array(sorted)));
+    }
+}
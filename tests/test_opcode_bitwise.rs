@@ -0,0 +1,80 @@
+#[allow(dead_code)]
+mod common;
+
+#[cfg(test)]
+mod test_opcode_bitwise {
+    use flint::vm::runner::*;
+    use flint::vm::opcodes::*;
+    use flint::bytecode;
+    use crate::common::run;
+
+    #[test]
+    fn test_and_or_xor() {
+        let stack = run(bytecode!(
+            IPUSH 12, IPUSH 10, AND,  // 0b1100 & 0b1010 = 0b1000
+            IPUSH 12, IPUSH 10, OR,   // 0b1110
+            IPUSH 12, IPUSH 10, XOR,  // 0b0110
+            HALT
+        )).stack;
+
+        assert_eq!(stack, vec![Value::Int(8), Value::Int(14), Value::Int(6)]);
+    }
+
+    #[test]
+    fn test_not_complements_bits() {
+        let stack = run(bytecode!(IPUSH 0, NOT, IPUSH -1, NOT, IPUSH 5, NOT, HALT)).stack;
+        assert_eq!(stack, vec![Value::Int(-1), Value::Int(0), Value::Int(-6)]);
+    }
+
+    #[test]
+    fn test_shl() {
+        let stack = run(bytecode!(
+            IPUSH 1, IPUSH 4, SHL,
+            IPUSH 1, IPUSH 31, SHL,   // Shifts into the sign bit
+            HALT
+        )).stack;
+        assert_eq!(stack, vec![Value::Int(16), Value::Int(i32::MIN)]);
+    }
+
+    #[test]
+    fn test_shr_is_arithmetic_and_ushr_is_logical() {
+        let stack = run(bytecode!(
+            IPUSH -16, IPUSH 2, SHR,
+            IPUSH -16, IPUSH 2, USHR,
+            IPUSH 16, IPUSH 2, USHR,
+            HALT
+        )).stack;
+        assert_eq!(stack, vec![
+            Value::Int(-4),
+            Value::Int(((-16i32 as u32) >> 2) as i32),
+            Value::Int(4),
+        ]);
+    }
+
+    #[test]
+    fn test_shift_count_is_taken_modulo_32() {
+        let stack = run(bytecode!(
+            IPUSH 1, IPUSH 33, SHL,
+            IPUSH 8, IPUSH -1, SHR,   // -1 & 31 == 31
+            HALT
+        )).stack;
+        assert_eq!(stack, vec![Value::Int(2), Value::Int(0)]);
+    }
+
+    #[test]
+    fn test_bitwise_rejects_floats() {
+        let logical = [op::AND, op::OR, op::XOR].map(|o| (o, "integers and booleans"));
+        let shifts = [op::SHL, op::SHR, op::USHR].map(|o| (o, "integers"));
+        for (opcode, supported) in logical.into_iter().chain(shifts) {
+            let mut code = bytecode!(FPUSH 1.5, IPUSH 1);
+            code.push(opcode);
+            let mut vm = VirtualMachine::new(code);
+
+            let name = op::get_info(opcode).unwrap().name;
+            assert_eq!(vm.run(), Err(format!("Type error: {} only supported for {}", name, supported)));
+        }
+
+        let mut vm = VirtualMachine::new(bytecode!(FPUSH 1.5, NOT));
+        assert_eq!(vm.run(), Err("Type error: NOT only supported for integers and booleans".to_string()));
+    }
+}
@@ -0,0 +1,137 @@
+#[allow(dead_code)]
+mod common;
+
+#[cfg(test)]
+mod test_opcode_boolean {
+    use flint::vm::runner::*;
+    use flint::vm::opcodes::*;
+    use flint::vm::assembler::Assembler;
+    use flint::bytecode;
+    use crate::common::run;
+
+    fn bools(values: &[bool]) -> Vec<Value> {
+        values.iter().map(|&b| Value::Bool(b)).collect()
+    }
+
+    #[test]
+    fn test_integer_comparisons() {
+        let stack = run(bytecode!(
+            BIPUSH 1, BIPUSH 2, EQ,
+            BIPUSH 1, BIPUSH 2, NE,
+            BIPUSH 1, BIPUSH 2, LT,
+            BIPUSH 2, BIPUSH 2, LE,
+            BIPUSH 1, BIPUSH 2, GT,
+            BIPUSH 2, BIPUSH 2, GE,
+            HALT
+        )).stack;
+        assert_eq!(stack, bools(&[false, true, true, true, false, true]));
+    }
+
+    #[test]
+    fn test_comparisons_promote_mixed_numbers() {
+        let stack = run(bytecode!(
+            BIPUSH 3, FPUSH 3.0, EQ,
+            LPUSH 5_000_000_000i64, IPUSH 2147483647, GT,
+            FPUSH 2.5, LPUSH 3, LT,
+            HALT
+        )).stack;
+        assert_eq!(stack, bools(&[true, true, true]));
+    }
+
+    #[test]
+    fn test_nan_is_unordered() {
+        let stack = run(bytecode!(
+            FPUSH f64::NAN, FPUSH f64::NAN, EQ,
+            FPUSH f64::NAN, FPUSH f64::NAN, NE,
+            FPUSH f64::NAN, BIPUSH 1, LT,
+            FPUSH f64::NAN, BIPUSH 1, GE,
+            HALT
+        )).stack;
+        assert_eq!(stack, bools(&[false, true, false, false]));
+    }
+
+    #[test]
+    fn test_equality_of_booleans() {
+        let stack = run(bytecode!(
+            BIPUSH 1, BIPUSH 2, LT, BIPUSH 3, BIPUSH 4, LT, EQ,
+            BIPUSH 1, BIPUSH 2, LT, BIPUSH 3, BIPUSH 4, GT, NE,
+            HALT
+        )).stack;
+        assert_eq!(stack, bools(&[true, true]));
+    }
+
+    #[test]
+    fn test_ordering_booleans_is_a_type_error() {
+        let mut vm = VirtualMachine::new(bytecode!(BIPUSH 1, BIPUSH 2, LT, BIPUSH 1, BIPUSH 2, LT, LT));
+        assert_eq!(vm.run(), Err("Type error: LT cannot compare Bool(true) and Bool(true)".to_string()));
+
+        let mut vm = VirtualMachine::new(bytecode!(BIPUSH 1, BIPUSH 2, LT, BIPUSH 1, EQ));
+        assert!(vm.run().unwrap_err().contains("EQ cannot compare"));
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let t = bytecode!(BIPUSH 1, BIPUSH 1, EQ);
+        let f = bytecode!(BIPUSH 1, BIPUSH 0, EQ);
+        let mut code = Vec::new();
+        for (a, b, logical) in [(&t, &f, op::AND), (&t, &f, op::OR), (&t, &t, op::XOR)] {
+            code.extend(a);
+            code.extend(b);
+            code.push(logical);
+        }
+        code.extend(&t);
+        code.push(op::NOT);
+
+        assert_eq!(run(code).stack, bools(&[false, true, false, false]));
+    }
+
+    #[test]
+    fn test_logical_operators_reject_mixed_types() {
+        let mut vm = VirtualMachine::new(bytecode!(BIPUSH 1, BIPUSH 1, EQ, BIPUSH 1, AND));
+        assert_eq!(vm.run(), Err("Type error: AND only supported for integers and booleans".to_string()));
+    }
+
+    #[test]
+    fn test_jt_and_jf() {
+        // BIPUSH(2) BIPUSH(2) LT(1) JT(5): the target 12 is the HALT after BIPUSH 99
+        let stack = run(bytecode!(BIPUSH 1, BIPUSH 2, LT, JT 12, BIPUSH 99, HALT)).stack;
+        assert_eq!(stack, vec![]);
+
+        let stack = run(bytecode!(BIPUSH 1, BIPUSH 2, LT, JF 12, BIPUSH 99, HALT)).stack;
+        assert_eq!(stack, vec![Value::Int(99)]);
+    }
+
+    #[test]
+    fn test_jt_requires_boolean() {
+        let mut vm = VirtualMachine::new(bytecode!(BIPUSH 1, JT 0));
+        assert_eq!(vm.run(), Err("Type error: JT expects a boolean on the stack".to_string()));
+    }
+
+    #[test]
+    fn test_compound_condition() {
+        // if (a < b && c) pushes 1, otherwise 0, with a = 3, b = 7 and c = true
+        let source = "
+            BIPUSH 3
+            STORE 0
+            BIPUSH 7
+            STORE 1
+            BIPUSH 1
+            BIPUSH 1
+            EQ
+            STORE 2
+            LOAD 0
+            LOAD 1
+            LT
+            LOAD 2
+            AND
+            JF else
+            BIPUSH 1
+            HALT
+            else:
+            BIPUSH 0
+            HALT
+        ";
+        let code = Assembler::new().assemble(source).unwrap();
+        assert_eq!(run(code).stack, vec![Value::Int(1)]);
+    }
+}
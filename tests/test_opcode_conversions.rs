@@ -0,0 +1,89 @@
+#[allow(dead_code)]
+mod common;
+
+#[cfg(test)]
+mod test_opcode_conversions {
+    use flint::vm::runner::*;
+    use flint::vm::opcodes::*;
+    use flint::bytecode;
+    use crate::common::{run, run_with};
+
+    const TRUNC: u8 = RoundingMode::Truncate as u8;
+    const FLOOR: u8 = RoundingMode::Floor as u8;
+    const CEIL: u8 = RoundingMode::Ceil as u8;
+    const ROUND: u8 = RoundingMode::Round as u8;
+
+    fn config(overflow: OverflowMode) -> VmConfig {
+        VmConfig { overflow, ..VmConfig::default() }
+    }
+
+    fn f2i(value: f64, rounding: u8) -> Value {
+        run(bytecode!(FPUSH value, F2I rounding)).stack[0]
+    }
+
+    #[test]
+    fn test_i2f() {
+        let stack = run(bytecode!(IPUSH -7, I2F, HALT)).stack;
+        assert_eq!(stack, vec![Value::Float(-7.0)]);
+    }
+
+    #[test]
+    fn test_f2i_rounding_modes() {
+        let cases = [
+            // value, trunc, floor, ceil, round
+            (2.5, 2, 2, 3, 3),
+            (-2.5, -2, -3, -2, -3),
+            (2.4, 2, 2, 3, 2),
+            (-0.6, 0, -1, 0, -1),
+            (7.0, 7, 7, 7, 7),
+        ];
+        for (value, trunc, floor, ceil, round) in cases {
+            assert_eq!(f2i(value, TRUNC), Value::Int(trunc), "trunc {}", value);
+            assert_eq!(f2i(value, FLOOR), Value::Int(floor), "floor {}", value);
+            assert_eq!(f2i(value, CEIL), Value::Int(ceil), "ceil {}", value);
+            assert_eq!(f2i(value, ROUND), Value::Int(round), "round {}", value);
+        }
+    }
+
+    #[test]
+    fn test_f2l_beyond_i32() {
+        let stack = run_with(bytecode!(FPUSH 1e12, F2L FLOOR, FPUSH -3.5, F2L ROUND, HALT), config(OverflowMode::Trapping)).stack;
+        assert_eq!(stack, vec![Value::Long(1_000_000_000_000), Value::Long(-4)]);
+    }
+
+    #[test]
+    fn test_out_of_range_and_nan_saturate() {
+        for mode in [OverflowMode::Wrapping, OverflowMode::Saturating] {
+            let stack = run_with(bytecode!(
+                FPUSH 1e10, F2I TRUNC,
+                FPUSH -1e10, F2I TRUNC,
+                FPUSH f64::NAN, F2I TRUNC,
+                FPUSH f64::INFINITY, F2L TRUNC,
+                HALT
+            ), config(mode)).stack;
+            assert_eq!(stack, vec![Value::Int(i32::MAX), Value::Int(i32::MIN), Value::Int(0), Value::Long(i64::MAX)]);
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_and_nan_trap() {
+        let trap = |code| run_with(code, config(OverflowMode::Trapping));
+
+        assert_eq!(trap(bytecode!(FPUSH 2147483647.5, F2I ROUND)).fault, Some("Runtime Error: Integer overflow in F2I".to_string()));
+        assert_eq!(trap(bytecode!(FPUSH 9.3e18, F2L TRUNC)).fault, Some("Runtime Error: Integer overflow in F2L".to_string()));
+        assert_eq!(trap(bytecode!(FPUSH f64::NAN, F2I FLOOR)).fault, Some("Runtime Error: Cannot convert NaN in F2I".to_string()));
+
+        // The exact bounds still convert
+        assert_eq!(trap(bytecode!(FPUSH -2147483648.0, F2I TRUNC)).stack, vec![Value::Int(i32::MIN)]);
+        assert_eq!(trap(bytecode!(FPUSH 2147483647.4, F2I ROUND)).stack, vec![Value::Int(i32::MAX)]);
+    }
+
+    #[test]
+    fn test_conversion_errors() {
+        let fault = |code| run(code).fault.unwrap();
+
+        assert!(fault(bytecode!(BIPUSH 1, F2I TRUNC)).contains("F2I expects a float"));
+        assert!(fault(bytecode!(FPUSH 1.0, I2F)).contains("I2F expects an integer"));
+        assert_eq!(fault(bytecode!(FPUSH 1.0, F2I 9)), "Runtime Error: Invalid rounding mode 9 in F2I");
+    }
+}
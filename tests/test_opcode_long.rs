@@ -0,0 +1,142 @@
+#[allow(dead_code)]
+mod common;
+
+#[cfg(test)]
+mod test_opcode_long {
+    use flint::vm::runner::*;
+    use flint::vm::opcodes::*;
+    use flint::bytecode;
+    use crate::common::{run, run_with};
+
+    #[test]
+    fn test_lpush() {
+        let stack = run(bytecode!(LPUSH 1_700_000_000_000i64, LPUSH -1, HALT)).stack;
+        assert_eq!(stack, vec![Value::Long(1_700_000_000_000), Value::Long(-1)]);
+    }
+
+    #[test]
+    fn test_long_arithmetic() {
+        let stack = run(bytecode!(
+            LPUSH 4_000_000_000i64, LPUSH 3, MUL,
+            LPUSH 10, LPUSH 4, SUB,
+            LPUSH -7, LPUSH 2, DIV,
+            LPUSH -7, LPUSH 2, MOD,
+            LPUSH 5, NEG,
+            HALT
+        )).stack;
+        assert_eq!(stack, vec![
+            Value::Long(12_000_000_000),
+            Value::Long(6),
+            Value::Long(-3),
+            Value::Long(-1),
+            Value::Long(-5),
+        ]);
+    }
+
+    #[test]
+    fn test_int_is_promoted_to_long() {
+        let stack = run(bytecode!(
+            IPUSH 2147483647, LPUSH 1, ADD,
+            LPUSH 10, BIPUSH 3, SUB,
+            HALT
+        )).stack;
+        assert_eq!(stack, vec![Value::Long(2_147_483_648), Value::Long(7)]);
+    }
+
+    #[test]
+    fn test_long_is_promoted_to_float() {
+        let stack = run(bytecode!(LPUSH 3, FPUSH 0.5, ADD, FPUSH 9.0, LPUSH 2, DIV, HALT)).stack;
+        assert_eq!(stack, vec![Value::Float(3.5), Value::Float(4.5)]);
+    }
+
+    #[test]
+    fn test_long_division_by_zero() {
+        let mut vm = VirtualMachine::new(bytecode!(LPUSH 1, BIPUSH 0, DIV));
+        assert_eq!(vm.run(), Err("Runtime Error: Division by zero".to_string()));
+    }
+
+    #[test]
+    fn test_long_overflow_follows_mode() {
+        assert_eq!(run(bytecode!(LPUSH i64::MAX, LPUSH 1, ADD, HALT)).stack, vec![Value::Long(i64::MIN)]);
+        assert_eq!(
+            run_with(bytecode!(LPUSH i64::MIN, LPUSH -1, DIV), VmConfig { overflow: OverflowMode::Trapping, ..VmConfig::default() }).fault,
+            Some("Runtime Error: Integer overflow in DIV".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cmp_mixed_widths() {
+        let stack = run(bytecode!(
+            LPUSH 5_000_000_000i64, IPUSH 2147483647, CMP,
+            BIPUSH 1, LPUSH 1, CMP,
+            LPUSH 2, FPUSH 2.5, CMP,
+            HALT
+        )).stack;
+        assert_eq!(stack, vec![Value::Int(1), Value::Int(0), Value::Int(-1)]);
+    }
+
+    #[test]
+    fn test_conditional_jump_on_long_compare() {
+        // Jumps over the BIPUSH 99 because 2^40 > 0
+        let stack = run(bytecode!(LPUSH 1i64 << 40, LPUSH 0, CMP, JG 26, BIPUSH 99, HALT)).stack;
+        assert_eq!(stack, vec![]);
+    }
+
+    #[test]
+    fn test_long_bitwise() {
+        let stack = run(bytecode!(
+            LPUSH 0xFF00_0000_0000i64, LPUSH 0x0F00_0000_0000i64, AND,
+            LPUSH 1, BIPUSH 40, SHL,
+            LPUSH -1, BIPUSH 60, USHR,
+            LPUSH 0, NOT,
+            HALT
+        )).stack;
+        assert_eq!(stack, vec![
+            Value::Long(0x0F00_0000_0000),
+            Value::Long(1 << 40),
+            Value::Long(0xF),
+            Value::Long(-1),
+        ]);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let stack = run(bytecode!(
+            IPUSH -5, I2L,
+            LPUSH 42, L2I,
+            LPUSH 4_294_967_297i64, L2I,
+            LPUSH 1i64 << 53, L2F,
+            HALT
+        )).stack;
+        assert_eq!(stack, vec![
+            Value::Long(-5),
+            Value::Int(42),
+            Value::Int(1),
+            Value::Float(9007199254740992.0),
+        ]);
+    }
+
+    #[test]
+    fn test_l2i_out_of_range_traps() {
+        assert_eq!(
+            run_with(bytecode!(LPUSH 1i64 << 31, L2I), VmConfig { overflow: OverflowMode::Trapping, ..VmConfig::default() }).fault,
+            Some("Runtime Error: Integer overflow in L2I".to_string())
+        );
+
+        let mut vm = VirtualMachine::with_config(
+            bytecode!(LPUSH -(1i64 << 40), L2I, HALT),
+            VmConfig { overflow: OverflowMode::Saturating, ..VmConfig::default() },
+        );
+        vm.execute();
+        assert_eq!(vm.stack, vec![Value::Int(i32::MIN)]);
+    }
+
+    #[test]
+    fn test_conversions_reject_wrong_types() {
+        let mut vm = VirtualMachine::new(bytecode!(BIPUSH 1, L2I));
+        assert!(vm.run().unwrap_err().contains("L2I expects a long"));
+
+        let mut vm = VirtualMachine::new(bytecode!(LPUSH 1, I2L));
+        assert!(vm.run().unwrap_err().contains("I2L expects an integer"));
+    }
+}
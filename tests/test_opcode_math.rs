@@ -0,0 +1,92 @@
+#[allow(dead_code)]
+mod common;
+
+#[cfg(test)]
+mod test_opcode_math {
+    use flint::vm::runner::*;
+    use flint::vm::opcodes::*;
+    use flint::bytecode;
+    use crate::common::run;
+
+    fn float(code: Vec<u8>) -> f64 {
+        match run(code).stack[..] {
+            [Value::Float(v)] => v,
+            ref other => panic!("Expected a single Float, got {:?}", other),
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-12, "{} != {}", actual, expected);
+    }
+
+    #[test]
+    fn test_sqrt() {
+        assert_eq!(float(bytecode!(FPUSH 2.25, SQRT)), 1.5);
+        assert_eq!(float(bytecode!(BIPUSH 49, SQRT)), 7.0);
+        assert!(float(bytecode!(FPUSH -1.0, SQRT)).is_nan());
+    }
+
+    #[test]
+    fn test_pow() {
+        assert_eq!(float(bytecode!(BIPUSH 2, BIPUSH 10, POW)), 1024.0);
+        assert_eq!(float(bytecode!(FPUSH 9.0, FPUSH 0.5, POW)), 3.0);
+        assert_eq!(float(bytecode!(LPUSH 10, IPUSH -2, POW)), 0.01);
+    }
+
+    #[test]
+    fn test_exp_and_log() {
+        assert_close(float(bytecode!(BIPUSH 1, EXP)), std::f64::consts::E);
+        assert_close(float(bytecode!(FPUSH std::f64::consts::E, LOG)), 1.0);
+        assert_eq!(float(bytecode!(BIPUSH 0, LOG)), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_trig() {
+        let half_pi = std::f64::consts::FRAC_PI_2;
+        assert_close(float(bytecode!(FPUSH half_pi, SIN)), 1.0);
+        assert_close(float(bytecode!(BIPUSH 0, COS)), 1.0);
+        assert_close(float(bytecode!(FPUSH std::f64::consts::FRAC_PI_4, TAN)), 1.0);
+    }
+
+    #[test]
+    fn test_abs_keeps_type() {
+        let stack = run(bytecode!(IPUSH -5, ABS, LPUSH -6, ABS, FPUSH -1.5, ABS, HALT)).stack;
+        assert_eq!(stack, vec![Value::Int(5), Value::Long(6), Value::Float(1.5)]);
+    }
+
+    #[test]
+    fn test_abs_of_min_follows_overflow_mode() {
+        assert_eq!(run(bytecode!(IPUSH i32::MIN, ABS, HALT)).stack, vec![Value::Int(i32::MIN)]);
+
+        let mut vm = VirtualMachine::with_config(
+            bytecode!(IPUSH i32::MIN, ABS),
+            VmConfig { overflow: OverflowMode::Trapping, ..VmConfig::default() },
+        );
+        assert_eq!(vm.run(), Err("Runtime Error: Integer overflow in ABS".to_string()));
+    }
+
+    #[test]
+    fn test_floor_and_ceil() {
+        let stack = run(bytecode!(FPUSH -2.5, FLOOR, FPUSH -2.5, CEIL, IPUSH 7, FLOOR, HALT)).stack;
+        assert_eq!(stack, vec![Value::Float(-3.0), Value::Float(-2.0), Value::Int(7)]);
+    }
+
+    #[test]
+    fn test_min_and_max_promote_like_add() {
+        let stack = run(bytecode!(
+            BIPUSH 3, BIPUSH 9, MIN,
+            BIPUSH 3, LPUSH 9, MAX,
+            BIPUSH 3, FPUSH 2.5, MIN,
+            FPUSH f64::NAN, FPUSH 1.0, MAX,
+            HALT
+        )).stack;
+        assert_eq!(stack, vec![Value::Int(3), Value::Long(9), Value::Float(2.5), Value::Float(1.0)]);
+    }
+
+    #[test]
+    fn test_math_rejects_non_numeric() {
+        let mut vm = VirtualMachine::new(vec![op::SQRT]);
+        vm.stack.push(Value::Char(b'a'));
+        assert_eq!(vm.run(), Err("Type error: SQRT only supported for numeric types".to_string()));
+    }
+}
@@ -0,0 +1,95 @@
+#[allow(dead_code)]
+mod common;
+
+#[cfg(test)]
+mod test_opcode_struct {
+    use flint::vm::runner::*;
+    use flint::vm::heap::{Object, StructLayout};
+    use flint::vm::history::History;
+    use flint::vm::opcodes::*;
+    use flint::bytecode;
+    use crate::common::{assemble, finish};
+
+    fn point() -> StructLayout {
+        StructLayout { name: "Point".to_string(), fields: vec!["x".to_string(), "y".to_string()] }
+    }
+
+    /// A machine for `code` with the single layout `point()`
+    fn with_point(code: Vec<u8>) -> VirtualMachine {
+        let mut vm = VirtualMachine::new(code);
+        vm.structs = vec![point()];
+        vm
+    }
+
+    #[test]
+    fn test_new_is_zero_filled() {
+        let vm = finish(with_point(bytecode!(NEW 0, HALT)));
+        assert_eq!(vm.stack, vec![Value::Ref(0)]);
+        assert_eq!(vm.heap.get(0), Some(&Object::Record { layout: 0, fields: vec![Value::Int(0); 2] }));
+    }
+
+    #[test]
+    fn test_set_and_get_fields() {
+        let vm = finish(with_point(bytecode!(
+            NEW 0, STORE 0,
+            LOAD 0, BIPUSH 3, SETFIELD 0,
+            LOAD 0, FPUSH 1.5, SETFIELD 1,
+            LOAD 0, GETFIELD 1,
+            LOAD 0, GETFIELD 0,
+            HALT
+        )));
+        assert_eq!(vm.stack, vec![Value::Float(1.5), Value::Int(3)]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(finish(with_point(bytecode!(NEW 1))).fault, Some("Runtime Error: Unknown struct 1".to_string()));
+        assert_eq!(finish(with_point(bytecode!(NEW 0, GETFIELD 2))).fault, Some("Runtime Error: Field 2 out of bounds for Point".to_string()));
+        assert_eq!(finish(with_point(bytecode!(BIPUSH 1, GETFIELD 0))).fault, Some("Type error: GETFIELD expects a record reference".to_string()));
+        assert_eq!(finish(with_point(bytecode!(BIPUSH 1, NEWARRAY, BIPUSH 0, SETFIELD 0))).fault, Some("Type error: SETFIELD expects a record reference".to_string()));
+        assert_eq!(finish(with_point(bytecode!(NEW 0, BIPUSH 0, ALOAD))).fault, Some("Type error: ALOAD expects an array reference".to_string()));
+    }
+
+    #[test]
+    fn test_step_back_undoes_setfield() {
+        let mut vm = with_point(bytecode!(NEW 0, DUP, BIPUSH 9, SETFIELD 1, HALT));
+        vm.history = Some(History::new());
+        vm.execute();
+        assert_eq!(vm.heap.get(0).unwrap().values(), &[Value::Int(0), Value::Int(9)]);
+
+        assert!(vm.step_back()); // HALT
+        assert!(vm.step_back());
+        assert_eq!(vm.heap.get(0).unwrap().values(), &[Value::Int(0), Value::Int(0)]);
+    }
+
+    #[test]
+    fn test_format_value() {
+        let vm = finish(assemble("
+            .struct Point x y
+            .struct Node value next
+            NEW Node
+            STORE 0
+            LOAD 0
+            NEW Point
+            DUP
+            FPUSH 2.5
+            SETFIELD Point.y
+            SETFIELD Node.value
+            LOAD 0
+            LOAD 0
+            SETFIELD Node.next
+            HALT
+        "));
+        assert_eq!(
+            vm.format_value(Value::Ref(0), &mut Vec::new()),
+            "Node { value: Point { x: 0, y: 2.50 }, next: Node {...} }"
+        );
+    }
+
+    #[test]
+    fn test_records_are_collected() {
+        let mut vm = finish(with_point(bytecode!(NEW 0, POP, NEW 0, HALT)));
+        assert_eq!(vm.collect_garbage(), 1);
+        assert_eq!(vm.heap.len(), 1);
+    }
+}
@@ -0,0 +1,100 @@
+#[allow(dead_code)]
+mod common;
+
+#[cfg(test)]
+mod test_opcode_switch {
+    use flint::vm::runner::*;
+    use flint::vm::opcodes::*;
+    use flint::vm::assembler::Assembler;
+    use flint::bytecode;
+    use crate::common::run;
+
+    /// Runs a switch over `selector` whose cases 10..=12 push 100, 101 and 102
+    /// and whose default pushes -1
+    fn switch(selector: i32) -> Vec<Value> {
+        let source = format!("
+            IPUSH {}
+            TABLESWITCH 10 default case10 case11 case12
+            case10:
+            IPUSH 100
+            JMP end
+            case11:
+            IPUSH 101
+            JMP end
+            case12:
+            IPUSH 102
+            JMP end
+            default:
+            IPUSH -1
+            end:
+            HALT
+        ", selector);
+        run(Assembler::new().assemble(&source).unwrap()).stack
+    }
+
+    #[test]
+    fn test_tableswitch_selects_case() {
+        assert_eq!(switch(10), vec![Value::Int(100)]);
+        assert_eq!(switch(11), vec![Value::Int(101)]);
+        assert_eq!(switch(12), vec![Value::Int(102)]);
+    }
+
+    #[test]
+    fn test_tableswitch_out_of_range_takes_default() {
+        assert_eq!(switch(9), vec![Value::Int(-1)]);
+        assert_eq!(switch(13), vec![Value::Int(-1)]);
+        assert_eq!(switch(i32::MIN), vec![Value::Int(-1)]);
+        assert_eq!(switch(i32::MAX), vec![Value::Int(-1)]);
+    }
+
+    #[test]
+    fn test_tableswitch_without_cases() {
+        // TABLESWITCH(13) BIPUSH(2) HALT
+        let vm = run(bytecode!(BIPUSH 0, TABLESWITCH 0, 17, [], BIPUSH 1, HALT));
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_tableswitch_type_error() {
+        assert_eq!(
+            run(bytecode!(FPUSH 1.0, TABLESWITCH 0, 0, [0])).fault,
+            Some("Type error: TABLESWITCH expects an integer selector".to_string())
+        );
+    }
+
+    #[test]
+    fn test_jmpi_jumps_to_popped_address() {
+        // BIPUSH(2) JMPI(1) BIPUSH(2) HALT
+        let vm = run(bytecode!(BIPUSH 5, JMPI, BIPUSH 1, HALT));
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn test_jmpi_errors() {
+        assert_eq!(run(bytecode!(IPUSH -1, JMPI)).fault, Some("Runtime Error: Invalid jump target -1 in JMPI".to_string()));
+        assert_eq!(run(bytecode!(FPUSH 1.0, JMPI)).fault, Some("Type error: JMPI expects an integer address".to_string()));
+    }
+
+    #[test]
+    fn test_computed_return_address() {
+        // A subroutine called twice returns through the address left on the stack
+        let source = "
+            ADDR back1
+            BIPUSH 2
+            JMP double
+            back1:
+            ADDR back2
+            SWP
+            JMP double
+            back2:
+            HALT
+            double:
+            DUP
+            ADD
+            SWP
+            JMPI
+        ";
+        let vm = run(Assembler::new().assemble(source).unwrap());
+        assert_eq!(vm.stack, vec![Value::Int(8)]);
+    }
+}
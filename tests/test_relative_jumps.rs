@@ -0,0 +1,101 @@
+#[allow(dead_code)]
+mod common;
+
+#[cfg(test)]
+mod test_relative_jumps {
+    use flint::vm::runner::*;
+    use flint::vm::opcodes::*;
+    use flint::vm::assembler::Assembler;
+    use flint::bytecode;
+    use crate::common::run;
+
+    // Counts down from 5, leaving 5 + 4 + 3 + 2 + 1 in memory[0]
+    const SUM: &str = "
+        BIPUSH 5
+        STORE 1
+        BIPUSH 0
+        STORE 0
+        loop:
+        LOAD 0
+        LOAD 1
+        ADD
+        STORE 0
+        LOAD 1
+        BIPUSH 1
+        SUB
+        DUP
+        STORE 1
+        BIPUSH 0
+        CMP
+        JG loop
+        HALT
+    ";
+
+    #[test]
+    fn test_relative_program_matches_absolute() {
+        let absolute = Assembler::new().assemble(SUM).unwrap();
+        let relative = Assembler::new().with_relative_jumps().assemble(SUM).unwrap();
+
+        assert_eq!(relative.len(), absolute.len() - 3, "JG shrinks from 5 to 2 bytes");
+        assert_eq!(run(absolute).memory[0], Value::Int(15));
+        assert_eq!(run(relative).memory[0], Value::Int(15));
+    }
+
+    #[test]
+    fn test_relative_code_runs_at_any_offset() {
+        let program = Assembler::new().with_relative_jumps().assemble(SUM).unwrap();
+
+        // Skip over 300 bytes of padding to the relocated copy
+        let mut code = vec![op::JMP];
+        code.extend(&305u32.to_be_bytes());
+        code.extend(vec![op::NOP; 300]);
+        code.extend(&program);
+
+        assert_eq!(run(code).memory[0], Value::Int(15));
+    }
+
+    #[test]
+    fn test_every_width_and_condition() {
+        let taken = |source: &str| {
+            let vm = run(Assembler::new().assemble(&format!("{}\nBIPUSH 0\nHALT\nyes:\nBIPUSH 1\nHALT", source)).unwrap());
+            vm.stack == vec![Value::Int(1)]
+        };
+
+        for width in [8, 16, 32] {
+            assert!(taken(&format!("BIPUSH 1\nBIPUSH 2\nCMP\nJL{} yes", width)));
+            assert!(!taken(&format!("BIPUSH 1\nBIPUSH 2\nCMP\nJGE{} yes", width)));
+            assert!(taken(&format!("BIPUSH 2\nBIPUSH 2\nCMP\nJLE{} yes", width)));
+            assert!(taken(&format!("BIPUSH 2\nBIPUSH 2\nCMP\nJE{} yes", width)));
+            assert!(!taken(&format!("BIPUSH 2\nBIPUSH 2\nCMP\nJNE{} yes", width)));
+            assert!(taken(&format!("BIPUSH 3\nBIPUSH 2\nCMP\nJG{} yes", width)));
+            assert!(taken(&format!("FPUSH NaN\nBIPUSH 2\nCMP\nJU{} yes", width)));
+            assert!(taken(&format!("BIPUSH 1\nBIPUSH 2\nLT\nJT{} yes", width)));
+            assert!(!taken(&format!("BIPUSH 1\nBIPUSH 2\nLT\nJF{} yes", width)));
+            assert!(taken(&format!("JMP{} yes", width)));
+        }
+    }
+
+    #[test]
+    fn test_backward_jumps() {
+        // Counts down from 3; the JG16 at 9 jumps back to the BIPUSH 1 at 2
+        let code = bytecode!(BIPUSH 3, BIPUSH 1, SUB, DUP, BIPUSH 0, CMP, JG16 -7, HALT);
+        assert_eq!(run(code).stack, vec![Value::Int(0)]);
+    }
+
+    #[test]
+    fn test_backward_jump_before_start_faults() {
+        assert_eq!(run(bytecode!(NOP, JMP8 -2)).fault, Some("Runtime Error: Invalid jump target -1 in JMP8".to_string()));
+    }
+
+    #[test]
+    fn test_type_errors_name_the_relative_form() {
+        assert_eq!(
+            run(bytecode!(BIPUSH 1, JT16 0)).fault,
+            Some("Type error: JT16 expects a boolean on the stack".to_string())
+        );
+        assert_eq!(
+            run(bytecode!(FPUSH 0.0, JL8 0)).fault,
+            Some("Type error: JL8 expects an integer on the stack from a CMP operation".to_string())
+        );
+    }
+}